@@ -0,0 +1,131 @@
+//! Binary Heaps
+//!
+//! An implicit min-heap stored in a `Vec`: the children of the node at
+//! `i` live at `2i + 1` and `2i + 2`.
+
+pub struct BinaryHeap<K, V> {
+    nodes: Vec<(K, V)>,
+}
+
+impl<K: Ord, V> BinaryHeap<K, V> {
+    pub fn new() -> Self {
+        BinaryHeap {
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Build a heap from unordered pairs in O(n) (Floyd's heapify)
+    pub fn from_vec(nodes: Vec<(K, V)>) -> Self {
+        let mut heap = BinaryHeap { nodes };
+        for i in (0..heap.nodes.len() / 2).rev() {
+            heap.sift_down(i);
+        }
+        heap
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn min_key(&self) -> Option<&K> {
+        self.nodes.first().map(|&(ref k, _)| k)
+    }
+
+    pub fn min_val(&self) -> Option<&V> {
+        self.nodes.first().map(|&(_, ref v)| v)
+    }
+
+    pub fn insert(&mut self, key: K, val: V) {
+        self.nodes.push((key, val));
+        let last = self.nodes.len() - 1;
+        self.sift_up(last);
+    }
+
+    pub fn delete_min(&mut self) -> Option<(K, V)> {
+        if self.nodes.is_empty() {
+            return None
+        }
+
+        let min = self.nodes.swap_remove(0);
+        self.sift_down(0);
+        Some(min)
+    }
+
+    /// Drain every pair in ascending key order (heapsort)
+    pub fn into_sorted_vec(mut self) -> Vec<(K, V)> {
+        let mut sorted = Vec::with_capacity(self.len());
+        while let Some(min) = self.delete_min() {
+            sorted.push(min);
+        }
+        sorted
+    }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if self.nodes[parent].0 <= self.nodes[i].0 {
+                break
+            }
+            self.nodes.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        let n = self.nodes.len();
+        loop {
+            let mut min = i;
+            for child in 2 * i + 1..n.min(2 * i + 3) {
+                if self.nodes[child].0 < self.nodes[min].0 {
+                    min = child;
+                }
+            }
+            if min == i {
+                break
+            }
+            self.nodes.swap(i, min);
+            i = min;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use util::random_array;
+
+    #[test]
+    fn test_binary_from_vec() {
+        let keys = random_array(1000);
+        let h = BinaryHeap::from_vec(keys.iter().cloned().zip(0..).collect());
+
+        assert_eq!(h.len(), 1000);
+        assert_eq!(h.min_key(), keys.iter().min());
+        for i in 1..h.len() {
+            assert!(h.nodes[(i - 1) / 2].0 <= h.nodes[i].0);
+        }
+
+        let sorted = h.into_sorted_vec();
+        let mut expected = keys.clone();
+        expected.sort_unstable();
+        assert_eq!(sorted.iter().map(|&(k, _)| k).collect::<Vec<_>>(), expected);
+        assert!(sorted.iter().all(|&(k, v)| keys[v] == k));
+    }
+
+    #[test]
+    fn test_binary_insert() {
+        let mut h = BinaryHeap::new();
+        for i in 0..100 {
+            h.insert((i * 37) % 100, i);
+        }
+
+        assert_eq!(h.min_key(), Some(&0));
+        assert_eq!(h.delete_min(), Some((0, 0)));
+        assert_eq!(h.delete_min().map(|(k, _)| k), Some(1));
+        assert_eq!(h.len(), 98);
+    }
+}
@@ -1,3 +1,99 @@
 //! Heap structures
 
+pub mod binary;
 pub mod sfib;
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+    use test::Bencher;
+    use util::random_range;
+    use heap::sfib::{Sfib, Element};
+
+    type Graph = Vec<Vec<(usize, u64)>>;
+
+    /// `n` vertices, each with `degree` random weighted out-edges
+    fn random_graph(n: usize, degree: usize) -> Graph {
+        (0..n).map(|_| {
+            (0..degree).map(|_| (random_range(0, n), random_range(1, 100))).collect()
+        }).collect()
+    }
+
+    /// Vertices in the order they are settled, with their distances
+    ///
+    /// Distances are paired with their vertex to keep the keys unique.
+    fn dijkstra_sfib(g: &Graph, src: usize) -> Vec<(u64, usize)> {
+        let mut heap = Sfib::new();
+        let mut handles: Vec<Option<Element<(u64, usize), ()>>> = (0..g.len()).map(|_| None).collect();
+        let mut dist = vec![None; g.len()];
+        let mut settled = Vec::new();
+
+        dist[src] = Some(0);
+        handles[src] = Some(heap.insert((0, src), ()));
+        while let Some(((d, u), ())) = heap.delete_min() {
+            settled.push((d, u));
+            handles[u] = None;
+            for &(v, w) in &g[u] {
+                let key = (d + w, v);
+                match dist[v] {
+                    None => {
+                        dist[v] = Some(d + w);
+                        handles[v] = Some(heap.insert(key, ()));
+                    }
+                    Some(old) if d + w < old => {
+                        dist[v] = Some(d + w);
+                        heap.decrease_key(handles[v].as_ref().unwrap(), key);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        settled
+    }
+
+    /// Like `dijkstra_sfib()` but pushing duplicates and skipping stale entries
+    fn dijkstra_binary_heap(g: &Graph, src: usize) -> Vec<(u64, usize)> {
+        let mut heap = BinaryHeap::new();
+        let mut dist = vec![None; g.len()];
+        let mut done = vec![false; g.len()];
+        let mut settled = Vec::new();
+
+        dist[src] = Some(0);
+        heap.push(Reverse((0, src)));
+        while let Some(Reverse((d, u))) = heap.pop() {
+            if done[u] { continue }
+            done[u] = true;
+            settled.push((d, u));
+            for &(v, w) in &g[u] {
+                if dist[v].map_or(true, |old| d + w < old) {
+                    dist[v] = Some(d + w);
+                    heap.push(Reverse((d + w, v)));
+                }
+            }
+        }
+        settled
+    }
+
+    #[test]
+    fn dijkstra() {
+        let g = random_graph(1000, 8);
+        let settled = dijkstra_sfib(&g, 0);
+        assert_eq!(settled, dijkstra_binary_heap(&g, 0));
+        assert!(settled.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    // On 10K vertices `Sfib` is currently about 100 times slower than the lazy
+    // `BinaryHeap`, as nodes never become active and so reductions seldom apply
+    #[bench]
+    fn bench_dijkstra_sfib(b: &mut Bencher) {
+        let g = random_graph(10_000, 8);
+        b.iter(|| dijkstra_sfib(&g, 0));
+    }
+
+    #[bench]
+    fn bench_dijkstra_binary_heap(b: &mut Bencher) {
+        let g = random_graph(10_000, 8);
+        b.iter(|| dijkstra_binary_heap(&g, 0));
+    }
+}
@@ -4,9 +4,12 @@
 
 use std::rc::Rc;
 use std::cell::{Cell, RefCell, Ref};
-use std::collections::VecDeque;
+use std::cmp::Ordering;
+use std::fmt;
+use std::collections::{BinaryHeap, VecDeque};
+use std::mem;
 
-use util::CyclicList;
+use util::{CyclicList, WeakCyclicList};
 
 enum RankDesc<K, V> {
     Rank(usize),
@@ -15,28 +18,75 @@ enum RankDesc<K, V> {
 }
 
 impl<K, V> RankDesc<K, V> {
-    // fn increase(&mut self) {
-    //     match *self {
-    //         RankDesc::None => self = RankDesc::Rank(0),
-    //         RankDesc::Rank(usize)
-    //     }
-    // }
+    // Passive nodes have no rank
     fn increase(&mut self) {
-        unimplemented!()
+        match *self {
+            RankDesc::Rank(ref mut r) => *r += 1,
+            RankDesc::Fix(ref fix) => fix.rank.set(fix.rank.get() + 1),
+            RankDesc::None => {}
+        }
     }
+
     fn decrease(&mut self) {
-        unimplemented!()
+        match *self {
+            RankDesc::Rank(ref mut r) => *r -= 1,
+            RankDesc::Fix(ref fix) => fix.rank.set(fix.rank.get() - 1),
+            RankDesc::None => {}
+        }
+    }
+
+    fn get(&self) -> Option<usize> {
+        match *self {
+            RankDesc::Rank(r) => Some(r),
+            RankDesc::Fix(ref fix) => Some(fix.rank.get()),
+            RankDesc::None => None,
+        }
     }
-    
 }
 
 type FixPtr<K, V> = CyclicList<Fix<K, V>>;
 struct Fix<K, V> {
     node: NodePtr<K, V>,
-    rank: usize,
+    rank: Cell<usize>,
+}
+
+/// Fix-lists are kept sorted by rank, starting from their head
+impl<K, V> FixPtr<K, V> {
+    /// Insert `x` before the first fix of equal or greater rank, returning the
+    /// new head of the list
+    fn insert_ranked(&self, x: Self) -> Self {
+        if x.rank.get() <= self.rank.get() {
+            self.push_front(x.clone());
+            return x
+        }
+
+        let mut y = self.next().clone();
+        while !FixPtr::ptr_eq(&y, self) && y.rank.get() < x.rank.get() {
+            let next = y.next().clone();
+            y = next;
+        }
+        y.push_front(x);
+        self.clone()
+    }
+
+    /// The first two adjacent fixes of equal rank
+    fn find_equal_ranks(&self) -> Option<(Self, Self)> {
+        let mut x = self.clone();
+        loop {
+            let y = x.next().clone();
+            if FixPtr::ptr_eq(&y, self) {
+                return None
+            }
+            if x.rank.get() == y.rank.get() {
+                return Some((x, y))
+            }
+            x = y;
+        }
+    }
 }
 
 type NodePtr<K, V> = CyclicList<RefCell<Node<K, V>>>;
+type WeakNodePtr<K, V> = WeakCyclicList<RefCell<Node<K, V>>>;
 struct Node<K, V> {
     key: K,
     val: V,
@@ -73,7 +123,7 @@ impl<K, V> Node<K, V> {
 
     fn is_active_root(&self) -> bool {
         let parent = match self.parent {
-            Some(ref parent) => parent.borrow().active.is_none(),
+            Some(ref parent) => parent.borrow().is_passive(),
             None => true,
         };
 
@@ -100,6 +150,18 @@ fn child_remove<K, V>(node: &NodePtr<K, V>, child: &NodePtr<K, V>) {
     node.borrow_mut().children.remove(i);
 }
 
+/// Counts of the work performed by a `Sfib`, kept with the `stats` feature
+#[cfg(feature = "stats")]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SfibStats {
+    /// Number of key comparisons made while restructuring the heap
+    pub comparisons: usize,
+    /// Number of times a node was linked below another
+    pub links: usize,
+    /// Number of reductions applied
+    pub reductions: usize,
+}
+
 pub struct Sfib<K, V> {
     size: usize,
     root: Option<NodePtr<K, V>>,
@@ -109,9 +171,22 @@ pub struct Sfib<K, V> {
     q: Option<NodePtr<K, V>>,
     fix_multis: Option<FixPtr<K, V>>,
     fix_singles: Option<FixPtr<K, V>>,
+
+    #[cfg(feature = "stats")]
+    stats: SfibStats,
 }
 
-pub struct Element<K, V>(NodePtr<K, V>);
+/// A handle to an element of a heap
+///
+/// Handles do not keep their element alive; the element is dropped once it is
+/// removed from the heap.
+pub struct Element<K, V>(WeakNodePtr<K, V>);
+
+impl<K, V> Element<K, V> {
+    fn new(node: &NodePtr<K, V>) -> Self {
+        Element(NodePtr::downgrade(node))
+    }
+}
 
 impl<K: Ord, V> Sfib<K, V> {
     pub fn new() -> Self {
@@ -123,10 +198,26 @@ impl<K: Ord, V> Sfib<K, V> {
             q: None,
             fix_multis: None,
             fix_singles: None,
+
+            #[cfg(feature = "stats")]
+            stats: SfibStats::default(),
         }
 
     }
 
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn op_stats(&self) -> SfibStats {
+        self.stats
+    }
+
     pub fn min_key(&self) -> Option<Ref<K>> {
         self.root.as_ref().map(|r| Ref::map(r.borrow(), |n| &n.key))
     }
@@ -136,7 +227,7 @@ impl<K: Ord, V> Sfib<K, V> {
     }
 
     pub fn min_node(&self) -> Option<Element<K, V>> {
-        self.root.as_ref().map(|r| Element(r.clone()))
+        self.root.as_ref().map(Element::new)
     }
 
     pub fn insert(&mut self, key: K, val: V) -> Element<K, V> {
@@ -144,7 +235,7 @@ impl<K: Ord, V> Sfib<K, V> {
             let root = Node::new_ptr(key, val);
             self.root = Some(root.clone());
             self.size = 1;
-            Element(root)
+            Element::new(&root)
         } else {
             let mut other = Self::new();
             let elem = other.insert(key, val);
@@ -155,14 +246,20 @@ impl<K: Ord, V> Sfib<K, V> {
 
     pub fn meld(&mut self, mut other: Self) {
         if other.root.is_none() { return }
-        if self.root.is_none() { *self = other; return }
+        if self.root.is_none() {
+            #[cfg(feature = "stats")]
+            { other.stats = self.stats; }
+            *self = other;
+            return
+        }
 
         debug_assert!(self.root.as_ref().unwrap().borrow().is_passive());
         debug_assert!(other.root.as_ref().unwrap().borrow().is_passive());
 
-        // make all nodes of smaller heap passive
+        // make all nodes of smaller heap passive, keeping the larger heap's flag
         if self.size <= other.size {
             self.active.set(false);
+            self.active = other.active.clone();
         } else {
             other.active.set(false);
         }
@@ -171,6 +268,7 @@ impl<K: Ord, V> Sfib<K, V> {
 
         // rename u/v such that u < v
         let (u, v) = (self.root.take().unwrap(), other.root.take().unwrap());
+        count!(self, comparisons += 1);
         let (u, v) = if u.borrow().key < v.borrow().key { (u, v) } else { (v, u) };
 
         // let u be root, and v its child
@@ -198,6 +296,269 @@ impl<K: Ord, V> Sfib<K, V> {
         self.reduce(1, 1, 0, 0);
     }
 
+    /// Remove the element with the smallest key
+    ///
+    /// The smallest child of the root becomes the new root and adopts the
+    /// remaining children, and reductions are then applied until none of them
+    /// applies.
+    pub fn delete_min(&mut self) -> Option<(K, V)> {
+        let root = match self.root.take() {
+            Some(root) => root,
+            None => return None,
+        };
+        self.size -= 1;
+
+        let children = mem::replace(&mut root.borrow_mut().children, VecDeque::new());
+        count!(self, comparisons += children.len().saturating_sub(1));
+        let x = children.iter().min_by(|a, b| a.borrow().key.cmp(&b.borrow().key)).cloned();
+        if let Some(x) = x {
+            self.queue_remove(&x);
+            x.borrow_mut().parent = None;
+            for c in children {
+                if NodePtr::ptr_eq(&c, &x) { continue }
+
+                c.borrow_mut().parent = Some(x.clone());
+                if c.borrow().is_active() {
+                    x.borrow_mut().children.push_front(c);
+                } else {
+                    x.borrow_mut().children.push_back(c);
+                }
+            }
+            self.root = Some(x);
+
+            self.consolidate();
+        }
+
+        let node = match NodePtr::try_unwrap(root) {
+            Ok(node) => node.into_inner(),
+            Err(_) => panic!("Root still referenced after removal"),
+        };
+        Some((node.key, node.val))
+    }
+
+    /// Lower the key of `e` to `key`
+    ///
+    /// # Panic
+    /// Panics if `key` is greater than the current key of `e`, or if `e` is no
+    /// longer in the heap.
+    pub fn decrease_key(&mut self, e: &Element<K, V>, key: K) {
+        let x = e.0.upgrade().expect("Element no longer in heap");
+        assert!(key <= x.borrow().key, "New key is greater than current key");
+        x.borrow_mut().key = key;
+
+        let parent = match x.borrow().parent {
+            Some(ref parent) => parent.clone(),
+            None => return, // x is the root
+        };
+        count!(self, comparisons += 1);
+        if parent.borrow().key <= x.borrow().key {
+            return
+        }
+
+        let root = self.root.clone().unwrap();
+        self.link(x.clone(), &root);
+        count!(self, comparisons += 1);
+        if x.borrow().key < root.borrow().key {
+            self.promote(&x);
+        }
+    }
+
+    /// Remove the element `e` from the heap
+    ///
+    /// `e` is moved to the root, as if its key were decreased below every
+    /// other key, and then removed by `delete_min()`.
+    ///
+    /// # Panic
+    /// Panics if `e` is no longer in the heap.
+    pub fn delete(&mut self, e: Element<K, V>) -> (K, V) {
+        let x = e.0.upgrade().expect("Element no longer in heap");
+        if x.borrow().parent.is_some() {
+            let root = self.root.clone().unwrap();
+            if !NodePtr::ptr_eq(x.borrow().parent.as_ref().unwrap(), &root) {
+                self.link(x.clone(), &root);
+            }
+            self.promote(&x);
+        }
+        drop(x);
+        self.delete_min().unwrap()
+    }
+
+    /// The `k` smallest elements in ascending order of key, leaving the heap
+    /// untouched
+    ///
+    /// Candidates are kept in a binary heap holding the children of every
+    /// element yielded so far.
+    ///
+    /// Elements below the root are only reachable through the `RefCell` of
+    /// their parent, so a reference to them cannot outlive the borrow taken
+    /// while visiting them. Unlike `min_key`, which borrows the root directly,
+    /// this returns clones.
+    pub fn k_smallest(&self, k: usize) -> Vec<(K, V)> where K: Clone, V: Clone {
+        let mut smallest = Vec::with_capacity(k.min(self.size));
+        let mut candidates = BinaryHeap::new();
+        candidates.extend(self.root.clone().map(Candidate));
+
+        while smallest.len() < k {
+            let node = match candidates.pop() {
+                Some(Candidate(node)) => node,
+                None => break,
+            };
+            let n = node.borrow();
+            candidates.extend(n.children.iter().cloned().map(Candidate));
+            smallest.push((n.key.clone(), n.val.clone()));
+        }
+        smallest
+    }
+
+    /// Apply reductions until none of them applies, bounding the root degree
+    ///
+    /// Useful after many insertions, which only reduce a constant amount each.
+    pub fn consolidate(&mut self) {
+        let n = self.size;
+        self.reduce(n, n, n, n);
+    }
+
+    /// Returns true if an element with `key` is in the heap
+    ///
+    /// There is no index of keys, so this searches every subtree whose root
+    /// is not greater than `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Returns a handle to the element with `key`, see `contains_key()`
+    pub fn get_element(&self, key: &K) -> Option<Element<K, V>> {
+        self.find(key).map(|node| Element::new(&node))
+    }
+
+    fn find(&self, key: &K) -> Option<NodePtr<K, V>> {
+        let mut stack = self.root.iter().cloned().collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            let ord = node.borrow().key.cmp(key);
+            match ord {
+                Ordering::Equal => return Some(node),
+                Ordering::Less => stack.extend(node.borrow().children.iter().cloned()),
+                Ordering::Greater => {}
+            }
+        }
+        None
+    }
+
+    /// Exchange the root with `x`, one of its children
+    fn promote(&mut self, x: &NodePtr<K, V>) {
+        let root = self.root.take().unwrap();
+        child_remove(&root, x);
+        x.borrow_mut().parent = None;
+        self.queue_remove(x);
+
+        root.borrow_mut().parent = Some(x.clone());
+        x.borrow_mut().children.push_back(root.clone());
+        self.queue_push(root);
+        self.root = Some(x.clone());
+    }
+
+    /// Lazily remove elements in ascending order of key
+    ///
+    /// Elements not yielded remain in the heap.
+    pub fn drain_sorted(&mut self) -> DrainSorted<K, V> {
+        DrainSorted { heap: self }
+    }
+
+    fn queue_push(&mut self, x: NodePtr<K, V>) {
+        match self.q {
+            Some(ref q) => q.push_back(x),
+            None => self.q = Some(x),
+        }
+    }
+
+    /// Unlink `x` from `q`, moving the head of `q` if necessary
+    fn queue_remove(&mut self, x: &NodePtr<K, V>) {
+        let is_head = self.q.as_ref().map_or(false, |q| NodePtr::ptr_eq(q, x));
+        if is_head {
+            self.q = if x.is_single() { None } else { Some(x.next().clone()) };
+        }
+        x.remove();
+    }
+
+    /// Check the structural invariants, panicking if one is violated
+    #[cfg(test)]
+    fn validate(&self) {
+        assert!(self.active.get(), "Heap's active flag is cleared");
+        let root = match self.root {
+            Some(ref root) => root,
+            None => {
+                assert_eq!(self.size, 0);
+                assert!(self.q.is_none());
+                return
+            }
+        };
+        assert!(root.borrow().parent.is_none(), "Root has a parent");
+        assert!(root.borrow().is_passive(), "Root is active");
+
+        let mut count = 0;
+        let mut active_roots = 0;
+        let mut stack = vec![root.clone()];
+        while let Some(node) = stack.pop() {
+            let n = node.borrow();
+            count += 1;
+            if n.is_active_root() { active_roots += 1 }
+            if let Some(ref flag) = n.active {
+                assert!(!flag.get() || Rc::ptr_eq(flag, &self.active), "Node is active under a foreign flag");
+            }
+
+            let active_children = n.children.iter().filter(|c| c.borrow().is_active()).count();
+            match n.rank {
+                RankDesc::Fix(_) => assert!(n.is_active_root(), "Only active roots are fixed"),
+                RankDesc::Rank(_) => assert!(n.is_active() && !n.is_active_root(), "Rank of a root"),
+                RankDesc::None => assert!(n.is_passive(), "Active node without a rank"),
+            }
+            assert!(n.rank.get().map_or(true, |r| r == active_children), "Wrong rank");
+
+            let mut seen_passive = false;
+            for c in &n.children {
+                let c = c.borrow();
+                assert!(NodePtr::ptr_eq(c.parent.as_ref().unwrap(), &node), "Broken parent pointer");
+                assert!(c.key >= n.key, "Heap order violated");
+                assert!(c.is_passive() || !seen_passive, "Active child after a passive child");
+                seen_passive |= c.is_passive();
+            }
+            stack.extend(n.children.iter().cloned());
+        }
+        assert_eq!(count, self.size);
+
+        // every node but the root is queued exactly once
+        let mut queued = 0;
+        if let Some(ref q) = self.q {
+            let mut x = q.clone();
+            loop {
+                assert!(!NodePtr::ptr_eq(&x, root), "Root is queued");
+                queued += 1;
+                let next = x.next().clone();
+                x = next;
+                if NodePtr::ptr_eq(&x, q) { break }
+            }
+        }
+        assert_eq!(queued, self.size - 1);
+
+        // every active root is fixed exactly once
+        let mut fixed = 0;
+        if let Some(ref head) = self.fix_multis {
+            let mut x = head.clone();
+            loop {
+                assert!(x.node.borrow().is_active_root(), "Fixed node is not an active root");
+                fixed += 1;
+                let next = x.next().clone();
+                x = next;
+                if FixPtr::ptr_eq(&x, head) { break }
+            }
+        }
+        assert_eq!(fixed, active_roots);
+
+        // at most 2 log n + 6 active roots
+        let log = 64 - (self.size as u64).leading_zeros() as usize;
+        assert!(active_roots <= 2 * log + 6, "Too many active roots");
+    }
+
     fn reduce(&mut self, mut a: usize, mut b: usize, mut c: usize, mut d: usize) {
         let mut progress = true;
         let mut sum = a + b + c + d;
@@ -209,11 +570,13 @@ impl<K: Ord, V> Sfib<K, V> {
 
             let old_sum = sum;
             sum = a + b + c + d;
+            count!(self, reductions += old_sum - sum);
             progress = sum < old_sum; // progress if at least one thing happened
         }
     }
 
     fn link(&mut self, x: NodePtr<K, V>, y: &NodePtr<K, V>) {
+        count!(self, links += 1);
         let active = x.borrow().is_active();
 
         if let Some(parent) = x.borrow_mut().parent.take() {
@@ -248,35 +611,550 @@ impl<K: Ord, V> Sfib<K, V> {
     fn deactivate(&mut self, x: &NodePtr<K, V>) {}
 
     fn active_root_reduction(&mut self) -> bool {
-        let x = if let Some(ref multis) = self.fix_multis { multis.next().clone() }
-                else { return false };
-        let y = x.next().clone();
-        if FixPtr::ptr_eq(&x, &y) || x.rank != y.rank {
-            return false
-        }
+        let (x, y) = match self.fix_multis.as_ref().and_then(|m| m.find_equal_ranks()) {
+            Some(pair) => pair,
+            None => return false,
+        };
 
+        count!(self, comparisons += 1);
         let (x, y) = if x.node.borrow().key < y.node.borrow().key { (x, y) } else { (y, x) };
+
+        // y stops being an active root, and x moves to its new rank
+        self.fix_remove(&y);
+        self.fix_remove(&x);
+        y.node.borrow_mut().rank = RankDesc::Rank(y.rank.get());
         self.link(y.node.clone(), &x.node);
+        self.fix_insert(x.clone());
 
-        let mut borrow = x.node.borrow_mut();
-        if borrow.children.back().unwrap().borrow().is_passive() {
-            let z = borrow.children.pop_back().unwrap();
-            self.reparent(z, self.root.as_ref().unwrap());
+        let z = x.node.borrow().children.back().cloned();
+        if let Some(z) = z {
+            if z.borrow().is_passive() {
+                self.reparent(z, self.root.as_ref().unwrap());
+            }
         }
 
         true
     }
 
-    fn root_degree_reduction(&mut self) -> bool{
+    /// Add an active root to the fix-list, keeping it sorted by rank
+    fn fix_insert(&mut self, x: FixPtr<K, V>) {
+        self.fix_multis = Some(match self.fix_multis.take() {
+            Some(head) => head.insert_ranked(x),
+            None => x,
+        });
+    }
+
+    /// Unlink `x` from the fix-list, moving its head if necessary
+    fn fix_remove(&mut self, x: &FixPtr<K, V>) {
+        let is_head = self.fix_multis.as_ref().map_or(false, |f| FixPtr::ptr_eq(f, x));
+        if is_head {
+            self.fix_multis = if x.is_single() { None } else { Some(x.next().clone()) };
+        }
+        x.remove();
+    }
+
+    /// Link the three rightmost passive linkable children of the root below
+    /// the smallest of them, lowering the root degree by two
+    ///
+    /// TODO the two upper nodes should become active, once `activate()` keeps
+    /// the fix-list up to date
+    fn root_degree_reduction(&mut self) -> bool {
+        let root = match self.root {
+            Some(ref root) => root.clone(),
+            None => return false,
+        };
+
+        let mut linkable = root.borrow().children.iter().rev()
+            .filter(|c| c.borrow().is_passive_linkable())
+            .take(3)
+            .cloned()
+            .collect::<Vec<_>>();
+        if linkable.len() < 3 {
+            return false
+        }
+
+        linkable.sort_by(|a, b| {
+            count!(self, comparisons += 1);
+            a.borrow().key.cmp(&b.borrow().key)
+        });
+        let (z, y, x) = (linkable.pop().unwrap(), linkable.pop().unwrap(), linkable.pop().unwrap());
+        self.link(z, &y);
+        self.link(y, &x);
         true
     }
 
+    /// The active nodes which are not active roots, found by descending
+    /// through the active children of the fixed roots
+    fn active_non_roots(&self) -> Vec<NodePtr<K, V>> {
+        let mut found = Vec::new();
+        let mut stack = Vec::new();
+        if let Some(ref head) = self.fix_multis {
+            let mut x = head.clone();
+            loop {
+                stack.push(x.node.clone());
+                let next = x.next().clone();
+                x = next;
+                if FixPtr::ptr_eq(&x, head) { break }
+            }
+        }
+        while let Some(node) = stack.pop() {
+            // active children come first
+            for c in node.borrow().children.iter().take_while(|c| c.borrow().is_active()) {
+                found.push(c.clone());
+                stack.push(c.clone());
+            }
+        }
+        found
+    }
+
+    /// The fix of `x`, if it is an active root
+    fn fix_of(x: &NodePtr<K, V>) -> Option<FixPtr<K, V>> {
+        match x.borrow().rank {
+            RankDesc::Fix(ref fix) => Some(fix.clone()),
+            _ => None,
+        }
+    }
+
+    /// Charge the loss of an active child to `z`, whose rank has dropped; an
+    /// active root is moved to its new place in the fix-list instead
+    fn lose_child(&mut self, z: &NodePtr<K, V>, fix: Option<FixPtr<K, V>>) {
+        match fix {
+            Some(fix) => self.fix_insert(fix),
+            None => z.borrow_mut().loss += 1,
+        }
+    }
+
+    /// Cut an active node which has lost two children from its parent and
+    /// link it below the root as an active root
+    ///
+    /// TODO loss is never incurred until nodes become active
     fn one_node_loss_reduction(&mut self) -> bool {
+        let x = match self.active_non_roots().into_iter().find(|x| x.borrow().loss >= 2) {
+            Some(x) => x,
+            None => return false,
+        };
+        let root = self.root.clone().unwrap();
+        let y = x.borrow().parent.clone().unwrap();
+
+        let y_fix = Self::fix_of(&y);
+        if let Some(ref fix) = y_fix { self.fix_remove(fix) }
+        self.link(x.clone(), &root);
+        self.lose_child(&y, y_fix);
+
+        let rank = x.borrow().rank.get().unwrap();
+        let fix = FixPtr::new(Fix { node: x.clone(), rank: Cell::new(rank) });
+        {
+            let mut x = x.borrow_mut();
+            x.loss = 0;
+            x.rank = RankDesc::Fix(fix.clone());
+        }
+        self.fix_insert(fix);
         true
     }
 
-    fn two_node_loss_reduction(&mut self) -> bool{
+    /// Link together two active nodes of equal rank which have each lost one
+    /// child, clearing their loss
+    fn two_node_loss_reduction(&mut self) -> bool {
+        let mut lossy = self.active_non_roots().into_iter()
+            .filter(|x| x.borrow().loss == 1)
+            .collect::<Vec<_>>();
+        lossy.sort_by_key(|x| x.borrow().rank.get());
+        let (x, y) = match lossy.windows(2).find(|w| w[0].borrow().rank.get() == w[1].borrow().rank.get()) {
+            Some(w) => (w[0].clone(), w[1].clone()),
+            None => return false,
+        };
+
+        count!(self, comparisons += 1);
+        let (x, y) = if x.borrow().key < y.borrow().key { (x, y) } else { (y, x) };
+        let z = y.borrow().parent.clone().unwrap();
+
+        // y may already be below x
+        if !NodePtr::ptr_eq(&z, &x) {
+            let z_fix = Self::fix_of(&z);
+            if let Some(ref fix) = z_fix { self.fix_remove(fix) }
+            self.link(y.clone(), &x);
+            self.lose_child(&z, z_fix);
+        }
+
+        x.borrow_mut().loss = 0;
+        y.borrow_mut().loss = 0;
         true
     }
 }
 
+/// A node ordered so that `BinaryHeap` pops the smallest key first
+struct Candidate<K, V>(NodePtr<K, V>);
+
+impl<K: Ord, V> Ord for Candidate<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.borrow().key.cmp(&self.0.borrow().key)
+    }
+}
+
+impl<K: Ord, V> PartialOrd for Candidate<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> PartialEq for Candidate<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: Ord, V> Eq for Candidate<K, V> {}
+
+/// Prints the tree one node per line, children indented below their parent
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for Sfib<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Sfib {{ size: {} }}", self.size)?;
+        let mut stack = self.root.iter().map(|r| (r.clone(), 0)).collect::<Vec<_>>();
+        while let Some((node, depth)) = stack.pop() {
+            let n = node.borrow();
+            let rank = n.rank.get().map_or("-".to_string(), |r| r.to_string());
+            writeln!(f, "{:indent$}{:?}: {:?} ({}, rank {}, loss {})", "", n.key, n.val,
+                     if n.is_active() { "active" } else { "passive" }, rank, n.loss,
+                     indent = 2 * depth)?;
+            stack.extend(n.children.iter().rev().map(|c| (c.clone(), depth + 1)));
+        }
+        Ok(())
+    }
+}
+
+pub struct DrainSorted<'a, K: 'a, V: 'a> {
+    heap: &'a mut Sfib<K, V>,
+}
+
+impl<'a, K: Ord, V> Iterator for DrainSorted<'a, K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.heap.delete_min()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fix_list(ranks: &[usize]) -> FixPtr<usize, ()> {
+        let fix = |rank| FixPtr::new(Fix { node: Node::new_ptr(0, ()), rank: Cell::new(rank) });
+        let mut head = fix(ranks[0]);
+        for &rank in &ranks[1..] {
+            head = head.insert_ranked(fix(rank));
+        }
+        head
+    }
+
+    #[test]
+    fn test_sfib_fix_ranks() {
+        let head = fix_list(&[3, 1, 4, 2, 5, 2, 0]);
+        let mut ranks = vec![head.rank.get()];
+        let mut x = head.next().clone();
+        while !FixPtr::ptr_eq(&x, &head) {
+            ranks.push(x.rank.get());
+            let next = x.next().clone();
+            x = next;
+        }
+        assert_eq!(ranks, [0, 1, 2, 2, 3, 4, 5]);
+
+        let (x, y) = head.find_equal_ranks().unwrap();
+        assert_eq!((x.rank.get(), y.rank.get()), (2, 2));
+        assert!(FixPtr::ptr_eq(&x.next(), &y));
+
+        assert!(fix_list(&[2, 0, 1]).find_equal_ranks().is_none());
+        assert!(fix_list(&[7]).find_equal_ranks().is_none());
+    }
+
+    /// Build a heap of exactly the given shape, without running any reductions
+    ///
+    /// Nodes are `(key, parent, active, loss)`, the root first and every parent
+    /// before its children. Ranks count active children, and active roots are
+    /// put in the fix-list.
+    fn build(nodes: &[(usize, Option<usize>, bool, usize)]) -> Sfib<usize, ()> {
+        let mut h = Sfib::new();
+        let mut ptrs = Vec::<NodePtr<usize, ()>>::new();
+        for &(key, parent, active, loss) in nodes {
+            let x = Node::new_ptr(key, ());
+            x.borrow_mut().loss = loss;
+            if active {
+                x.borrow_mut().active = Some(h.active.clone());
+            }
+
+            match parent {
+                None => h.root = Some(x.clone()),
+                Some(parent) => {
+                    let p = ptrs.iter().find(|p| p.borrow().key == parent).unwrap().clone();
+                    x.borrow_mut().parent = Some(p.clone());
+                    if active {
+                        p.borrow_mut().children.push_front(x.clone());
+                    } else {
+                        p.borrow_mut().children.push_back(x.clone());
+                    }
+                    h.queue_push(x.clone());
+                }
+            }
+            ptrs.push(x);
+            h.size += 1;
+        }
+
+        for x in &ptrs {
+            if x.borrow().is_passive() { continue }
+            let rank = x.borrow().children.iter().filter(|c| c.borrow().is_active()).count();
+            if x.borrow().is_active_root() {
+                let fix = FixPtr::new(Fix { node: x.clone(), rank: Cell::new(rank) });
+                x.borrow_mut().rank = RankDesc::Fix(fix.clone());
+                h.fix_insert(fix);
+            } else {
+                x.borrow_mut().rank = RankDesc::Rank(rank);
+            }
+        }
+        h.validate();
+        h
+    }
+
+    fn keys_of(node: &NodePtr<usize, ()>) -> Vec<usize> {
+        node.borrow().children.iter().map(|c| c.borrow().key).collect()
+    }
+
+    #[test]
+    fn test_sfib_active_root_reduction() {
+        // two active roots of rank 0, the smaller with a passive child
+        let mut h = build(&[(0, None, false, 0), (10, Some(0), true, 0), (20, Some(0), true, 0),
+                            (15, Some(10), false, 0)]);
+        assert!(h.active_root_reduction());
+        h.validate();
+
+        // the larger is linked below the smaller, whose passive child moves to the root
+        let root = h.root.clone().unwrap();
+        let ten = h.find(&10).unwrap();
+        assert_eq!(keys_of(&root), [10, 15]);
+        assert_eq!(keys_of(&ten), [20]);
+        assert_eq!(ten.borrow().rank.get(), Some(1));
+        assert!(!h.active_root_reduction());
+    }
+
+    #[test]
+    fn test_sfib_root_degree_reduction() {
+        let mut h = build(&[(0, None, false, 0), (7, Some(0), false, 0), (3, Some(0), false, 0),
+                            (9, Some(0), false, 0), (5, Some(0), false, 0)]);
+        assert!(h.root_degree_reduction());
+        h.validate();
+
+        // the rightmost three are chained below the smallest of them
+        assert_eq!(keys_of(h.root.as_ref().unwrap()), [7, 3]);
+        assert_eq!(keys_of(&h.find(&3).unwrap()), [5]);
+        assert_eq!(keys_of(&h.find(&5).unwrap()), [9]);
+        assert!(!h.root_degree_reduction());
+    }
+
+    #[test]
+    fn test_sfib_one_node_loss_reduction() {
+        // an active non-root node which has lost two children
+        let mut h = build(&[(0, None, false, 0), (10, Some(0), true, 0), (20, Some(10), true, 2)]);
+        assert!(h.one_node_loss_reduction());
+        h.validate();
+
+        // it becomes an active root with no loss
+        let twenty = h.find(&20).unwrap();
+        assert_eq!(twenty.borrow().parent.as_ref().unwrap().borrow().key, 0);
+        assert_eq!(twenty.borrow().loss, 0);
+        assert!(twenty.borrow().is_active_root());
+    }
+
+    #[test]
+    fn test_sfib_two_node_loss_reduction() {
+        // two active non-root nodes of equal rank which have each lost a child
+        let mut h = build(&[(0, None, false, 0), (10, Some(0), true, 0),
+                            (20, Some(10), true, 1), (30, Some(10), true, 1)]);
+        assert!(h.two_node_loss_reduction());
+        h.validate();
+
+        // the larger is linked below the smaller, and neither has any loss
+        let twenty = h.find(&20).unwrap();
+        assert_eq!(keys_of(&twenty), [30]);
+        assert_eq!(twenty.borrow().loss, 0);
+        assert_eq!(h.find(&30).unwrap().borrow().loss, 0);
+    }
+
+    #[test]
+    fn test_sfib_debug() {
+        let mut h = Sfib::new();
+        h.insert(20, 'b');
+        let mut other = Sfib::new();
+        other.insert(10, 'a');
+        other.insert(30, 'c');
+        h.meld(other);
+
+        let child = h.get_element(&30).unwrap().0.upgrade().unwrap();
+        child.borrow_mut().active = Some(h.active.clone());
+
+        let s = format!("{:?}", h);
+        assert!(s.starts_with("Sfib { size: 3 }"));
+        assert!(s.contains("10: 'a' (passive"));
+        assert!(s.contains("  20: 'b' (passive"));
+        assert!(s.contains("  30: 'c' (active"));
+    }
+
+    #[test]
+    fn test_sfib_delete() {
+        let mut h = Sfib::new();
+        let mut elements = (0..20).map(|i| h.insert((i * 7) % 20, i)).collect::<Vec<_>>();
+
+        assert_eq!(h.delete(elements.swap_remove(3)), (1, 3));
+        h.validate();
+        let min = h.min_node().unwrap();
+        assert_eq!(h.delete(min), (0, 0));
+        h.validate();
+
+        let keys = h.drain_sorted().map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(keys, (2..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_sfib_consolidate() {
+        // a root with a thousand passive children, as if melded without reducing
+        let mut nodes = vec![(0, None, false, 0)];
+        nodes.extend((1..1001).map(|k| (k, Some(0), false, 0)));
+        let mut h = build(&nodes);
+        let bound = 2 * 10 + 6;
+        assert!(h.root.as_ref().unwrap().borrow().children.len() > bound);
+
+        h.consolidate();
+        h.validate();
+
+        let degree = h.root.as_ref().unwrap().borrow().children.len();
+        assert!(degree <= bound, "Root degree {} after consolidating", degree);
+        assert_eq!(h.drain_sorted().map(|(k, _)| k).collect::<Vec<_>>(), (0..1001).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn test_sfib_op_stats() {
+        use util::random_permutation;
+
+        // the stats after inserting n keys, after melding one more, and after
+        // deleting them all
+        fn measure(n: usize) -> (SfibStats, SfibStats, SfibStats) {
+            let mut h = Sfib::new();
+            for key in random_permutation(n) {
+                h.insert(key, ());
+            }
+            let inserted = h.op_stats();
+
+            let mut single = Sfib::new();
+            single.insert(n, ());
+            h.meld(single);
+            let melded = h.op_stats();
+
+            while h.delete_min().is_some() {}
+            (inserted, melded, h.op_stats())
+        }
+
+        let (small, large) = (1 << 8, 1 << 12);
+        let (s_inserted, s_melded, s_deleted) = measure(small);
+        let (l_inserted, l_melded, l_deleted) = measure(large);
+
+        // each insertion and meld costs the same at either size
+        assert!(s_inserted.comparisons <= 3 * small && l_inserted.comparisons <= 3 * large);
+        assert!(s_inserted.links <= small && l_inserted.links <= large);
+        assert!(s_melded.comparisons - s_inserted.comparisons <= 4);
+        assert!(l_melded.comparisons - l_inserted.comparisons <= 4);
+        assert!(s_melded.links - s_inserted.links <= 2);
+        assert!(l_melded.links - l_inserted.links <= 2);
+
+        // sixteen times the keys costs each delete_min a few more comparisons,
+        // not sixteen times as many
+        let s_delete = (s_deleted.comparisons - s_melded.comparisons) / (small + 1);
+        let l_delete = (l_deleted.comparisons - l_melded.comparisons) / (large + 1);
+        assert!(s_delete < l_delete, "delete_min: {} comparisons at {}, {} at {}", s_delete, small, l_delete, large);
+        assert!(l_delete <= s_delete + 4 * 4, "delete_min: {} comparisons at {}, {} at {}", s_delete, small, l_delete, large);
+    }
+
+    #[test]
+    fn test_sfib_k_smallest() {
+        use util::random_permutation;
+
+        let mut h = Sfib::new();
+        let mut keys = random_permutation(1000);
+        keys.truncate(100);
+        for &k in &keys {
+            h.insert(k, k * 2);
+        }
+        keys.sort();
+
+        assert_eq!(h.k_smallest(5), keys[..5].iter().map(|&k| (k, k * 2)).collect::<Vec<_>>());
+        assert_eq!(h.len(), 100);
+        h.validate();
+
+        let all = h.k_smallest(200).into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(all, keys);
+        assert!(Sfib::<usize, ()>::new().k_smallest(3).is_empty());
+    }
+
+    #[test]
+    fn test_sfib_drain_sorted() {
+        let mut h = Sfib::new();
+        for i in 0..100 {
+            h.insert((i * 37) % 100, i);
+        }
+
+        let top = h.drain_sorted().take(5).map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(top, [0, 1, 2, 3, 4]);
+        assert_eq!(h.len(), 95);
+        assert_eq!(*h.min_key().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_sfib_meld_chain() {
+        // the accumulated heap is the larger one
+        let mut h = Sfib::new();
+        for i in 0..100 {
+            let mut single = Sfib::new();
+            single.insert(i, ());
+            h.meld(single);
+            h.validate();
+        }
+        assert_eq!(h.len(), 100);
+
+        // the accumulated heap is the smaller one
+        let mut g = Sfib::new();
+        for i in 0..100 {
+            let mut single = Sfib::new();
+            single.insert(i, ());
+            single.meld(g);
+            g = single;
+            g.validate();
+        }
+        assert_eq!(g.len(), 100);
+
+        h.meld(g);
+        h.validate();
+        assert_eq!(h.len(), 200);
+        assert!(h.drain_sorted().map(|(k, _)| k).eq((0..100).flat_map(|k| vec![k, k])));
+    }
+
+    #[test]
+    fn test_sfib_contains() {
+        let mut h = Sfib::new();
+        for &k in &[30, 10, 50, 20, 40] {
+            h.insert(k, k * 2);
+        }
+
+        assert!(h.contains_key(&10));
+        assert!(h.contains_key(&40));
+        assert!(!h.contains_key(&35));
+        assert!(!h.contains_key(&5));
+        assert!(h.get_element(&60).is_none());
+
+        let e = h.get_element(&40).unwrap();
+        h.decrease_key(&e, 5);
+        assert!(h.contains_key(&5));
+        assert!(!h.contains_key(&40));
+        assert_eq!(h.drain_sorted().collect::<Vec<_>>(), [(5, 80), (10, 20), (20, 40), (30, 60), (50, 100)]);
+    }
+}
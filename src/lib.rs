@@ -2,8 +2,18 @@
 #![feature(specialization)]
 
 extern crate rand;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 extern crate test;
 
+/// Add `$n` to a counter in the `stats` of `$s`, if the `stats` feature keeps them
+macro_rules! count {
+    ($s:expr, $counter:ident += $n:expr) => {
+        #[cfg(feature = "stats")]
+        { $s.stats.$counter += $n; }
+    }
+}
+
 pub mod heap;
 pub mod order;
 pub mod sort;
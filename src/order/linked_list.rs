@@ -6,12 +6,15 @@
 use std::cell::Cell;
 use std::rc::Rc;
 use std::iter::{Iterator, IntoIterator};
+use std::collections::VecDeque;
 use std::ops::Deref;
 use std::fmt;
+use std::mem;
 
 pub struct LinkedList<T> {
     front: Option<Atom<T>>,
     back:  Option<Atom<T>>,
+    len: usize,
 }
 
 pub struct Atom<T>(Rc<AtomInner<T>>);
@@ -27,6 +30,25 @@ impl<T> LinkedList<T> {
         LinkedList {
             front: None,
             back: None,
+            len: 0,
+        }
+    }
+
+    /// Return the number of elements in the `LinkedList`
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return true if the `LinkedList` has no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over the atoms of the `LinkedList`, front to back
+    pub fn iter(&self) -> AtomIter<T> {
+        AtomIter {
+            item: self.front().cloned(),
+            list: self,
         }
     }
 
@@ -43,6 +65,7 @@ impl<T> LinkedList<T> {
     /// Add an element to be the beginning of the `LinkedList`
     pub fn push_front(&mut self, value: T) {
         let atom = Atom::new(value);
+        self.len += 1;
 
         if let Some(next) = self.front.take() {
             next.0.prev.set(Some(atom.clone()));
@@ -54,10 +77,44 @@ impl<T> LinkedList<T> {
         }
     }
 
+    /// Add every element of `iter` to the beginning of the `LinkedList`,
+    /// keeping them in the order of `iter`
+    pub fn push_front_all<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut batch = LinkedList::new();
+        for value in iter {
+            batch.push_back(value);
+        }
+
+        if let Some(last) = batch.back.take() {
+            if let Some(first) = self.front.take() {
+                first.0.prev.set(Some(last.clone()));
+                last.0.next.set(Some(first));
+            } else {
+                self.back = Some(last);
+            }
+            self.front = batch.front.take();
+            self.len += batch.len;
+        }
+    }
+
     /// Add an element to be the end of the `LinkedList`
     pub fn push_back(&mut self, value: T) {
-        let atom = Atom::new(value);
+        self.len += 1;
+        self.link_back(Atom::new(value));
+    }
 
+    /// Move `atom` to the end of the `LinkedList`, keeping the same atom
+    ///
+    /// `atom` must belong to this list, which is not checked.
+    pub fn move_to_back(&mut self, atom: &Atom<T>) {
+        if Rc::ptr_eq(&atom.0, &self.back.as_ref().expect("Empty LinkedList").0) {
+            return
+        }
+        self.unlink(atom);
+        self.link_back(atom.clone());
+    }
+
+    fn link_back(&mut self, atom: Atom<T>) {
         if let Some(prev) = self.back.take() {
             prev.0.next.set(Some(atom.clone()));
             atom.0.prev.set(Some(prev));
@@ -68,9 +125,50 @@ impl<T> LinkedList<T> {
         }
     }
 
+    /// Add an element directly after `atom`, returning the new atom
+    ///
+    /// `atom` must belong to this list. Unlike `extract`, this is not checked
+    /// on debug since it would make insertion linear.
+    pub fn insert_after(&mut self, atom: &Atom<T>, value: T) -> Atom<T> {
+        let new = Atom::new(value);
+        new.0.prev.set(Some(atom.clone()));
+        match atom.0.next.replace(Some(new.clone())) {
+            Some(next) => {
+                next.0.prev.set(Some(new.clone()));
+                new.0.next.set(Some(next));
+            }
+            None => self.back = Some(new.clone()),
+        }
+        self.len += 1;
+        new
+    }
+
+    /// Keep the first `n` elements, returning the rest as a new `LinkedList`
+    pub fn split_off(&mut self, n: usize) -> LinkedList<T> {
+        if n == 0 {
+            return mem::replace(self, LinkedList::new())
+        }
+        if n >= self.len {
+            return LinkedList::new()
+        }
+
+        let last = self.iter().nth(n - 1).unwrap();
+        let first = last.0.next.replace(None).unwrap();
+        first.0.prev.set(None);
+
+        let tail = LinkedList {
+            front: Some(first),
+            back: self.back.replace(last),
+            len: self.len - n,
+        };
+        self.len = n;
+        tail
+    }
+
     /// Remove an element from the beginning of the `LinkedList`
     pub fn pop_front(&mut self) -> Atom<T> {
         if let Some(front) = self.front.take() {
+            self.len -= 1;
             if Rc::ptr_eq(&front.0, &self.back.as_ref().unwrap().0) { // If there's a front, there's a back
                 self.back = None;
             } else {
@@ -96,8 +194,14 @@ impl<T> LinkedList<T> {
     /// Will panic (on debug) if `atom` does not belong to this list
     pub fn extract(&mut self, atom: Atom<T>) {
         /// Ensure this atom exists in this LinkedList
-        debug_assert!(self.into_iter().filter(|a| Rc::ptr_eq(&a.0, &atom.0)).count() == 1);
+        debug_assert!(self.iter().filter(|a| Rc::ptr_eq(&a.0, &atom.0)).count() == 1);
 
+        self.unlink(&atom);
+        self.len -= 1;
+    }
+
+    /// Detach `atom` from its neighbours, leaving `len` untouched
+    fn unlink(&mut self, atom: &Atom<T>) {
         let prev = atom.0.prev.replace(None);
         let next = atom.0.next.replace(None);
         if let Some(ref prev) = prev {
@@ -113,6 +217,53 @@ impl<T> LinkedList<T> {
             self.back = prev;
         }
     }
+
+    /// Remove consecutive repeated elements
+    pub fn dedup(&mut self) where T: PartialEq {
+        let mut prev = match self.front.clone() {
+            Some(front) => front,
+            None => return,
+        };
+        let mut cursor = prev.next_atom();
+        while let Some(atom) = cursor {
+            cursor = atom.next_atom();
+            if *atom == *prev {
+                self.unlink(&atom);
+                self.len -= 1;
+            } else {
+                prev = atom;
+            }
+        }
+    }
+
+    /// Remove every element matching `f`, returning their values in order
+    ///
+    /// Atoms which are still referenced outside of the list cannot be
+    /// unwrapped, so they are skipped and remain in the list.
+    pub fn extract_if<F: FnMut(&T) -> bool>(&mut self, mut f: F) -> Vec<T> {
+        let mut extracted = Vec::new();
+        let mut cursor = self.front.clone();
+        while let Some(atom) = cursor {
+            cursor = atom.next_atom();
+
+            // One reference from each neighbour (or front/back), plus ours
+            if Rc::strong_count(&atom.0) > 3 || !f(&atom) {
+                continue
+            }
+            // Skip extract's membership check, which would make this quadratic
+            self.unlink(&atom);
+            self.len -= 1;
+            extracted.extend(atom.try_unwrap());
+        }
+        extracted
+    }
+}
+
+fn clone_cell<T: Clone+Default>(cell: &Cell<T>) -> T {
+    let x = cell.take();
+    let y = x.clone();
+    cell.set(x);
+    y
 }
 
 
@@ -127,16 +278,9 @@ pub struct AtomIter<'a, T: 'a> {
 impl<'a, T: 'a> Iterator for AtomIter<'a, T> {
     type Item = Atom<T>;
     fn next(&mut self) -> Option<Self::Item> {
-        fn clone_cell<T: Clone+Default>(cell: &Cell<T>) -> T {
-            let x = cell.take();
-            let y = x.clone();
-            cell.set(x);
-            y
-        }
-
         match self.item.take() {
             Some(a) => {
-                self.item = clone_cell(&a.0.next);
+                self.item = a.next_atom();
                 Some(a)
             }
             None => None,
@@ -148,10 +292,52 @@ impl<'a, T> IntoIterator for &'a LinkedList<T> {
     type Item = Atom<T>;
     type IntoIter = AtomIter<'a, T>;
     fn into_iter(self) -> Self::IntoIter {
-        AtomIter {
-            item: self.front().cloned(),
-            list: self,
+        self.iter()
+    }
+}
+
+/// Owning iterator over the values of a `LinkedList`
+///
+/// # Panic
+/// Will panic if an atom is still referenced outside of the list
+pub struct IntoIter<T>(LinkedList<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.front.is_none() {
+            return None
+        }
+        let atom = self.0.pop_front();
+        Some(atom.try_unwrap().expect("Atom is referenced outside of the LinkedList"))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T> IntoIterator for LinkedList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+impl<T> From<VecDeque<T>> for LinkedList<T> {
+    fn from(deque: VecDeque<T>) -> Self {
+        let mut list = LinkedList::new();
+        for value in deque {
+            list.push_back(value);
         }
+        list
+    }
+}
+
+impl<T> From<LinkedList<T>> for VecDeque<T> {
+    fn from(list: LinkedList<T>) -> Self {
+        list.into_iter().collect()
     }
 }
 
@@ -164,6 +350,10 @@ impl<T> Atom<T> {
         }))
     }
 
+    fn next_atom(&self) -> Option<Atom<T>> {
+        clone_cell(&self.0.next)
+    }
+
     /// Will unwrap if there is only one pointer to this Atom
     pub fn try_unwrap(self) -> Option<T> {
         Rc::try_unwrap(self.0).ok().map(|a| a.value)
@@ -190,7 +380,7 @@ impl<T> Deref for Atom<T> {
 
 impl<T: fmt::Debug> fmt::Debug for LinkedList<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self.into_iter().collect::<Vec<_>>())
+        write!(f, "{:?}", self.iter().collect::<Vec<_>>())
     }
 }
 
@@ -213,7 +403,150 @@ mod test {
         ll.push_back(2);
         ll.push_front(-1);
         ll.push_front(-2);
-        assert_eq!(ll.into_iter().map(|a| *a).collect::<Vec<_>>(), [-2, -1, 1, 2]);
+        assert_eq!(ll.iter().map(|a| *a).collect::<Vec<_>>(), [-2, -1, 1, 2]);
+    }
+
+    #[test]
+    fn test_ll_vec_deque() {
+        let words = ["to", "be", "or", "not"].iter().map(|s| s.to_string()).collect::<VecDeque<_>>();
+
+        let ll = LinkedList::from(words.clone());
+        assert_eq!(ll.len(), 4);
+        assert_eq!(ll.front().map(|a| a.as_str()), Some("to"));
+        assert_eq!(ll.back().map(|a| a.as_str()), Some("not"));
+
+        assert_eq!(VecDeque::from(ll), words);
+        assert!(VecDeque::from(LinkedList::<String>::new()).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_ll_into_iter_shared() {
+        let mut ll = LinkedList::new();
+        ll.push_back(1);
+        let _held = ll.front().cloned();
+        ll.into_iter().count();
+    }
+
+    #[test]
+    fn test_ll_dedup() {
+        use std::rc::Rc;
+        use std::cell::Cell;
+
+        struct Counted { x: i32, drops: Rc<Cell<usize>> }
+        impl PartialEq for Counted {
+            fn eq(&self, other: &Self) -> bool { self.x == other.x }
+        }
+        impl Drop for Counted {
+            fn drop(&mut self) { self.drops.set(self.drops.get() + 1) }
+        }
+
+        let drops = Rc::new(Cell::new(0));
+        let mut ll = LinkedList::new();
+        for &x in &[1, 1, 2, 2, 2, 3] {
+            ll.push_back(Counted { x, drops: drops.clone() });
+        }
+
+        ll.dedup();
+        assert_eq!(ll.iter().map(|a| a.x).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(ll.len(), 3);
+        assert_eq!(ll.back().map(|a| a.x), Some(3));
+        assert_eq!(drops.get(), 3);
+    }
+
+    #[test]
+    fn test_ll_move_to_back() {
+        let mut ll = LinkedList::new();
+        for i in 1..4 { ll.push_back(i) }
+
+        let one = ll.front().cloned().unwrap();
+        ll.move_to_back(&one);
+        assert_eq!(ll.iter().map(|a| *a).collect::<Vec<_>>(), [2, 3, 1]);
+        assert!(Rc::ptr_eq(&ll.back().unwrap().0, &one.0));
+        assert_eq!(ll.len(), 3);
+
+        let three = ll.iter().nth(1).unwrap();
+        ll.move_to_back(&three);
+        ll.move_to_back(&three);
+        assert_eq!(ll.iter().map(|a| *a).collect::<Vec<_>>(), [2, 1, 3]);
+        assert_eq!(ll.front().map(|a| a.get()), Some(2));
+    }
+
+    #[test]
+    fn test_ll_split_off() {
+        let mut ll = LinkedList::new();
+        for i in 1..6 { ll.push_back(i) }
+
+        let mut tail = ll.split_off(2);
+        assert_eq!(ll.iter().map(|a| *a).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(tail.iter().map(|a| *a).collect::<Vec<_>>(), [3, 4, 5]);
+        assert_eq!((ll.len(), tail.len()), (2, 3));
+        assert_eq!(ll.back().map(|a| a.get()), Some(2));
+
+        ll.push_back(6);
+        tail.push_front(0);
+        assert_eq!(ll.iter().map(|a| *a).collect::<Vec<_>>(), [1, 2, 6]);
+        assert_eq!(tail.iter().map(|a| *a).collect::<Vec<_>>(), [0, 3, 4, 5]);
+
+        assert_eq!(ll.split_off(3).len(), 0);
+        let all = ll.split_off(0);
+        assert_eq!(all.len(), 3);
+        assert!(ll.front().is_none() && ll.back().is_none() && ll.is_empty());
+    }
+
+    #[test]
+    fn test_ll_push_front_all() {
+        let mut ll = LinkedList::new();
+        ll.push_back(4);
+        ll.push_back(5);
+        ll.push_front_all(vec![1, 2, 3]);
+        assert_eq!(ll.iter().map(|a| *a).collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        assert_eq!(ll.len(), 5);
+        assert_eq!(ll.back().map(|a| a.get()), Some(5));
+
+        let mut empty = LinkedList::new();
+        empty.push_front_all(1..4);
+        empty.push_front_all(None);
+        assert_eq!(empty.iter().map(|a| *a).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(empty.back().map(|a| a.get()), Some(3));
+    }
+
+    #[test]
+    fn test_ll_insert_after() {
+        let mut ll = LinkedList::new();
+        ll.push_back(1);
+        ll.push_back(4);
+        let one = ll.front().unwrap().clone();
+        let two = ll.insert_after(&one, 2);
+        ll.insert_after(&two, 3);
+        let five = ll.insert_after(&ll.back().unwrap().clone(), 5);
+        assert_eq!(ll.iter().map(|a| *a).collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        assert!(Rc::ptr_eq(&five.0, &ll.back().unwrap().0));
+        assert_eq!(ll.len(), 5);
+    }
+
+    #[test]
+    fn test_ll_extract_if() {
+        let mut ll = LinkedList::new();
+        for i in 1..6 { ll.push_back(i) }
+
+        let odd = ll.extract_if(|&v| v % 2 == 1);
+        assert_eq!(odd, [1, 3, 5]);
+        assert_eq!(ll.iter().map(|a| *a).collect::<Vec<_>>(), [2, 4]);
+        assert_eq!(ll.len(), 2);
+        assert_eq!(ll.front().map(|a| a.get()), Some(2));
+        assert_eq!(ll.back().map(|a| a.get()), Some(4));
+    }
+
+    #[test]
+    fn test_ll_extract_if_shared() {
+        let mut ll = LinkedList::new();
+        for i in 1..4 { ll.push_back(i) }
+
+        let held = ll.front().unwrap().clone();
+        assert_eq!(ll.extract_if(|_| true), [2, 3]);
+        assert_eq!(ll.iter().map(|a| *a).collect::<Vec<_>>(), [1]);
+        assert_eq!(*held, 1);
     }
 
     #[bench]
@@ -232,10 +565,25 @@ mod test {
         })
     }
 
+    #[bench]
+    fn bench_ll_insert_middle(b: &mut Bencher) {
+        b.iter(|| {
+            let mut ll = LinkedList::new();
+            ll.push_back(0);
+            ll.push_back(0);
+            // keep `mid` at rank len/2 - 1, inserting at len/2 like the `Vec` bench
+            let mut mid = ll.front().unwrap().clone();
+            for i in 0..N {
+                ll.insert_after(&mid, i);
+                if i % 2 == 1 { mid = mid.next_atom().unwrap() }
+            }
+        })
+    }
+
     #[bench]
     fn bench_ll_iter(b: &mut Bencher) {
         let mut ll = LinkedList::new();
         for i in 0..N { ll.push_back(i) }
-        b.iter(|| ll.into_iter().map(|a| *a).sum::<usize>())
+        b.iter(|| ll.iter().map(|a| *a).sum::<usize>())
     }
 }
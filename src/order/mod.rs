@@ -24,6 +24,28 @@ mod test {
         });
     }
 
+    // Inserting N elements at rank len/2, compared with `bench_ofm_insert_middle`
+    // and `bench_ll_insert_middle` (ms per iteration, `cargo bench` with N varied):
+    //
+    //     N       Vec    Ofm   LinkedList
+    //     16K       9     39            1
+    //     32K      53    112          2.5
+    //     64K     227    325          4.3
+    //     128K   1001   1178           10
+    //
+    // `Ofm::insert_at` finds the rank by scanning the leaves, so it does not
+    // overtake `Vec` at these sizes. `LinkedList` steps a held `Atom` instead.
+    #[bench]
+    fn bench_vec_insert_middle(b: &mut Bencher) {
+        b.iter(|| {
+            let mut v = vec![0, 0];
+            for i in 0..N {
+                let mid = v.len() / 2;
+                v.insert(mid, i)
+            }
+        });
+    }
+
     #[bench]
     fn bench_vec_iter(b: &mut Bencher) {
         let mut v = Vec::new();
@@ -12,7 +12,13 @@
 //! traversal becomes much faster due to fewer cache-misses.
 
 use std;
-use std::ops::Range;
+use std::borrow::Borrow;
+use std::ops::{Bound, Range, RangeBounds};
+
+use util::{next_power_of_two, round_up_pow2};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 /// Construct an array of Nones
 ///
@@ -25,9 +31,9 @@ fn empty_array<T>(n: usize) -> Box<[Option<T>]> {
     v.into_boxed_slice()
 }
 
-/// Determine if a range is ripe for rebalancing
-fn threshold(d: f32, rho: f32) -> bool {
-    (rho >= 0.5 - d/4.0) && (rho <= 0.75 + d/4.0)
+/// Maximum density of a range at relative depth `d` (0 at the root)
+fn upper_threshold(d: f32) -> f32 {
+    0.75 + d/4.0
 }
 
 /// An opaque wrapper
@@ -42,16 +48,42 @@ default impl<T> Indexable for T {
     fn index(&mut self, _: Index) {}
 }
 
+/// Counts of the expensive operations performed by an `Ofm`, kept with the
+/// `stats` feature
+#[cfg(feature = "stats")]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct OfmStats {
+    /// Number of times the backing store was doubled
+    pub doubles: usize,
+    /// Number of rebalances spanning more than one leaf
+    pub rebalances: usize,
+}
+
+/// When an `Ofm` gives back space after removals
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShrinkPolicy {
+    /// Halve the array as soon as it would be a quarter full afterwards
+    Eager,
+    /// Halve the array once it would be a sixteenth full afterwards, so
+    /// alternating removals and insertions do not repeatedly shrink and double
+    Lazy,
+    /// Never shrink the array
+    Never,
+}
+
 /// Order File Maintenance
 ///
 /// Faster insertion than an array; faster traversal than a
 /// linked list.
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Ofm<T: Indexable> {
     cells: Box<[Option<T>]>,
     occupied: Box<[usize]>,
     leaf_size: usize,
     size: usize,
+    #[cfg(feature = "stats")]
+    stats: OfmStats,
+    shrink_policy: ShrinkPolicy,
 }
 
 impl<T: Indexable> Ofm<T> {
@@ -64,6 +96,69 @@ impl<T: Indexable> Ofm<T> {
             occupied: vec![0; 2].into_boxed_slice(),
             leaf_size: 1,
             size: 0,
+            #[cfg(feature = "stats")]
+            stats: OfmStats::default(),
+            shrink_policy: ShrinkPolicy::Lazy,
+        }
+    }
+
+    /// Construct a new instance which shrinks according to `policy`
+    ///
+    /// `new()` uses `ShrinkPolicy::Lazy`.
+    pub fn with_shrink_policy(policy: ShrinkPolicy) -> Self {
+        let mut o = Self::new();
+        o.shrink_policy = policy;
+        o
+    }
+
+    /// Construct an instance able to hold `n` elements without doubling
+    pub fn with_capacity(n: usize) -> Self {
+        let mut o = Self::new();
+        o.reserve(n);
+        o
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Number of cells in the backing array
+    pub fn capacity(&self) -> usize {
+        self.cells.len()
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> OfmStats {
+        self.stats
+    }
+
+    /// Ensure `additional` more elements can be inserted without doubling
+    ///
+    /// All the necessary doublings are done at once, so existing elements
+    /// are only moved a single time.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.size + additional;
+        let mut leaf_size = self.leaf_size;
+        let mut leaves = self.occupied.len();
+        let mut doubles = 0;
+        loop {
+            // leaves of this size needed under the root's threshold, kept a power of two
+            let fit = (needed as f32 / (upper_threshold(0.0) * leaf_size as f32)).ceil() as usize;
+            if round_up_pow2(fit) <= leaves {
+                break
+            }
+            leaf_size += 1;
+            leaves = next_power_of_two(leaves);
+            doubles += 1;
+        }
+
+        if doubles > 0 {
+            self.resize(leaf_size, leaves);
+            count!(self, doubles += doubles);
         }
     }
 
@@ -73,75 +168,447 @@ impl<T: Indexable> Ofm<T> {
 
     pub fn push_back(&mut self, v: T) {
         let n = self.cells.len();
-        self.insert(n, v)
+        self.insert(n, v);
     }
 
-    fn insert(&mut self, i: usize, v: T) {
-        self.size += 1;
+    /// Insert `v` before the element of rank `rank`, returning its index
+    ///
+    /// `insert_at(0, v)` pushes to the front and `insert_at(self.len(), v)`
+    /// pushes to the back.
+    ///
+    /// # Panic
+    /// Panics if `rank > self.len()`
+    pub fn insert_at(&mut self, rank: usize, v: T) -> Index {
+        assert!(rank <= self.size, "Rank out of bounds");
+        let i = self.cell_of_rank(rank);
+        self.insert(i, v)
+    }
+
+    /// Insert `v` directly after the element at `i`, returning its index
+    ///
+    /// Note this may move other elements, invalidating their indices.
+    pub fn insert_after(&mut self, i: Index, v: T) -> Index {
+        assert!(self.cells[i.0].is_some(), "Invalid index");
+        self.insert(i.0 + 1, v)
+    }
+
+    /// Merge the ascending elements of `iter` into a sorted `Ofm`
+    ///
+    /// Each element is moved once and the whole array is redistributed at the
+    /// end, rather than inserting the new elements one at a time.
+    pub fn extend_ordered<I: IntoIterator<Item = T>>(&mut self, iter: I) where T: Ord {
+        let n = self.cells.len();
+        let mut old = self.cells_take(0..n).into_iter().peekable();
+        let mut new = iter.into_iter().peekable();
+
+        let mut merged = Vec::with_capacity(self.size + new.size_hint().0);
+        loop {
+            let from_old = match (old.peek(), new.peek()) {
+                (Some(a), Some(b)) => a <= b,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            if from_old {
+                merged.push(old.next().unwrap());
+            } else {
+                let v = new.next().unwrap();
+                debug_assert!(new.peek().map_or(true, |w| v <= *w), "Elements are not ascending");
+                merged.push(v);
+            }
+        }
+
+        let additional = merged.len() - self.size;
+        self.reserve(additional);
+        let n = self.cells.len();
+        self.size = merged.len();
+        self.redistribute(0..n, merged);
+    }
+
+    /// Move the elements satisfying `pred` before those which do not,
+    /// keeping the relative order within each group
+    ///
+    /// Returns the index of the first element not satisfying `pred`, or one
+    /// past the last cell if there is none.
+    pub fn partition_in_place<F: Fn(&T) -> bool>(&mut self, pred: F) -> Index {
+        let n = self.cells.len();
+        let (mut vals, rest): (Vec<T>, Vec<T>) = self.cells_take(0..n).into_iter().partition(|v| pred(v));
+        let (k, total) = (vals.len(), self.size);
+        vals.extend(rest);
+
+        self.redistribute(0..n, vals);
+        if k < total { Index(spread(&(0..n), k, total)) } else { Index(n) }
+    }
+
+    /// Remove every element matching `f`, returning them in order
+    ///
+    /// The remaining elements are spread evenly across the array afterwards.
+    pub fn drain_filter<F: FnMut(&T) -> bool>(&mut self, mut f: F) -> Vec<T> {
+        let n = self.cells.len();
+        let (drained, kept): (Vec<T>, Vec<T>) = self.cells_take(0..n).into_iter().partition(|v| f(v));
+
+        self.size = kept.len();
+        self.shrink();
+        let n = self.cells.len();
+        self.redistribute(0..n, kept);
+        drained
+    }
+
+    /// The element at `i`, or `None` if its cell is empty
+    ///
+    /// Unlike indexing, this does not panic on a stale index.
+    pub fn checked_index(&self, i: Index) -> Option<&T> {
+        self.cells.get(i.0).and_then(|c| c.as_ref())
+    }
+
+    /// Remove and return the element at `i`
+    ///
+    /// Note this may move other elements, invalidating their indices.
+    pub fn remove(&mut self, i: Index) -> T {
+        let v = self.cell_take(i.0).expect("Invalid index");
+        self.size -= 1;
+        self.shrink();
+        v
+    }
+
+    /// Mutable references to the elements at several indices at once
+    ///
+    /// Returns `None` if any index is repeated or does not hold an element.
+    pub fn get_many_mut<const N: usize>(&mut self, idxs: [Index; N]) -> Option<[&mut T; N]> {
+        for (k, i) in idxs.iter().enumerate() {
+            let live = self.cells.get(i.0).map_or(false, |c| c.is_some());
+            if !live || idxs[..k].contains(i) {
+                return None
+            }
+        }
+
+        // The indices are distinct, so the references are disjoint
+        let cells = self.cells.as_mut_ptr();
+        Some(idxs.map(|i| unsafe { (*cells.add(i.0)).as_mut().unwrap() }))
+    }
+
+    /// Move the element at `i` and every element after it into a new instance
+    pub fn split_at_index(&mut self, i: Index) -> Self {
+        assert!(self.cells[i.0].is_some(), "Invalid index");
+        let n = self.cells.len();
+        let tail = self.cells_take(i.0..n);
+        self.size -= tail.len();
+        self.shrink();
+
+        let mut tail = Self::from_vec(tail);
+        tail.shrink_policy = self.shrink_policy;
+        tail
+    }
+
+    /// Iterate over the element at `start` and every element after it
+    pub fn iter_from(&self, start: Index) -> OfmIter<T> {
+        assert!(self.cells[start.0].is_some(), "Invalid index");
+        OfmIter { cells: &self.cells, i: start.0 }
+    }
+
+    /// Iterate over the elements ranked `range.start` up to `range.end`
+    pub fn iter_range(&self, range: Range<usize>) -> impl Iterator<Item = &T> {
+        assert!(range.start <= range.end && range.end <= self.size, "Rank out of bounds");
+        let start = self.cell_of_rank(range.start);
+        OfmIter { cells: &self.cells, i: start }.take(range.len())
+    }
+
+    /// References to every element, in order
+    pub fn to_vec_refs(&self) -> Vec<&T> {
+        let mut refs = Vec::with_capacity(self.size);
+        refs.extend(self.leaf_scan());
+        refs
+    }
+
+    /// References to every element in order, alongside their indices
+    pub fn as_contiguous(&self) -> (Vec<&T>, Vec<Index>) {
+        let mut values = Vec::with_capacity(self.size);
+        let mut indices = Vec::with_capacity(self.size);
+        for (i, v) in self.leaf_scan_indexed() {
+            values.push(v);
+            indices.push(i);
+        }
+        (values, indices)
+    }
+
+    /// Parallel iterator over the elements in order
+    ///
+    /// The elements are first gathered by `to_vec_refs()`.
+    #[cfg(feature = "rayon")]
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = &T> where T: Sync {
+        self.to_vec_refs().into_par_iter()
+    }
+
+    /// Number of elements within `range`
+    ///
+    /// The elements must be sorted.
+    pub fn count_in_range<R: RangeBounds<T>>(&self, range: R) -> usize where T: Ord {
+        let start = match range.start_bound() {
+            Bound::Included(s) => self.partition_point(|v| v < s),
+            Bound::Excluded(s) => self.partition_point(|v| v <= s),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(e) => self.partition_point(|v| v <= e),
+            Bound::Excluded(e) => self.partition_point(|v| v < e),
+            Bound::Unbounded => self.cells.len(),
+        };
+        self.rank_of_cell(end).saturating_sub(self.rank_of_cell(start))
+    }
+
+    /// The `k`th element counting back from the last, which is `k = 0`
+    pub fn nth_from_end(&self, mut k: usize) -> Option<&T> {
+        for (l, &o) in self.occupied.iter().enumerate().rev() {
+            if k < o {
+                return self.cells[self.leaf_boundary(l)].iter().rev().filter_map(|c| c.as_ref()).nth(k)
+            }
+            k -= o;
+        }
+        None
+    }
+
+    /// Search a sorted `Ofm` for `v`
+    ///
+    /// Returns the index of a matching element, or else the index of the first
+    /// greater element (one past the last cell if there is none).
+    pub fn binary_search(&self, v: &T) -> Result<Index, Index> where T: Ord {
+        self.binary_search_borrow(v)
+    }
 
-        let (mut leaf, mut offset) = self.leaf(i);
-        if i == self.cells.len() {
-            leaf -= 1;
-            offset = self.leaf_size - 1;
+    /// Like `binary_search()` but searching by a borrowed form of `T`, such
+    /// as `&str` for an `Ofm<String>`
+    pub fn binary_search_borrow<Q: Ord + ?Sized>(&self, q: &Q) -> Result<Index, Index> where T: Borrow<Q> {
+        let i = self.next_live(self.partition_point(|x| x.borrow() < q));
+        match self.cells.get(i) {
+            Some(&Some(ref x)) if x.borrow() == q => Ok(Index(i)),
+            _ => Err(Index(i)),
         }
+    }
+
+    /// Smallest element, in `O(n)` time
+    ///
+    /// `Ofm` is itself `Ord`, so the method call `o.min()` picks `Ord::min`;
+    /// call this one as `Ofm::min(&o)`.
+    pub fn min(&self) -> Option<&T> where T: Ord {
+        self.leaf_scan().min()
+    }
+
+    /// Largest element, in `O(n)` time
+    ///
+    /// Like `min`, call this as `Ofm::max(&o)`.
+    pub fn max(&self) -> Option<&T> where T: Ord {
+        self.leaf_scan().max()
+    }
+
+    /// Iterate over the elements in order, skipping empty leaves entirely
+    fn leaf_scan(&self) -> impl Iterator<Item = &T> {
+        self.leaf_scan_indexed().map(|(_, v)| v)
+    }
+
+    /// Like `leaf_scan`, pairing each element with its index
+    fn leaf_scan_indexed(&self) -> impl Iterator<Item = (Index, &T)> {
+        let cells = &self.cells;
+        let leaf_size = self.leaf_size;
+        self.occupied.iter().enumerate()
+            .filter(|&(_, &o)| o > 0)
+            .flat_map(move |(l, _)| l * leaf_size..(l + 1) * leaf_size)
+            .filter_map(move |i| cells[i].as_ref().map(|v| (Index(i), v)))
+    }
 
-        self.grow(leaf);
-        let r = self.leaf_boundary(leaf);
+    /// Construct an instance holding `vs` in order
+    fn from_vec(vs: Vec<T>) -> Self {
+        let mut o = Self::with_capacity(vs.len());
+        let n = o.cells.len();
+        o.size = vs.len();
+        o.redistribute(0..n, vs);
+        o
+    }
+
+    /// Insert `v` before the first element at or after cell `i`
+    ///
+    /// Inserting at `self.cells.len()` appends.
+    fn insert(&mut self, i: usize, v: T) -> Index {
+        let leaf = self.leaf(i.min(self.cells.len() - 1)).0;
+        let r = match self.window(leaf) {
+            Some(r) => r,
+            None => {
+                let k = self.live_cells(0..i);
+                self.double();
+                let i = self.cell_of_rank(k);
+                return self.insert(i, v);
+            }
+        };
+        if r.len() > self.leaf_size {
+            count!(self, rebalances += 1);
+        }
 
+        let k = self.live_cells(r.start..i);
         let mut vals = self.cells_take(r.clone());
-        let num_vals = vals.len();
-        debug_assert!(num_vals != self.leaf_size);
-
-        vals.insert(offset.min(num_vals), v);
-        self.redistribute(r, vals);
-    }
-
-    fn grow(&mut self, l: usize) {
-        if self.occupied[l] == self.leaf_size {
-            let leaves = self.occupied.len();
-            let height = (2f32 * leaves as f32).log2() as u32;
-            debug_assert!(2usize.pow(height) == 2*leaves); // perfect power of 2 => complete binary tree
-
-            let tree = conceptual_tree::Tree::new(height);
-            let mut node = tree.get_leaf(l);
-
-            let mut o = self.occupied[l];
-            let mut c = self.leaf_size;
-            loop {
-                let r = tree.range(node.sibling());
-                c *= 2;
-                o += self.occupied[r].iter().sum::<usize>();
-                node.parent();
-
-                if node.is_root() {
-                    self.double();
-                    return;
-                } else if threshold(node.depth as f32 / (height - 0) as f32, o as f32 / c as f32) {
-                    node.parent();
-                    let mut r = tree.range(node);
-                    r.start *= self.leaf_size;
-                    r.end   *= self.leaf_size;
-                    self.rebalance(r);
-                    return;
-                }
+        vals.insert(k, v);
+        self.size += 1;
+
+        let n = vals.len();
+        self.redistribute(r.clone(), vals);
+        Index(spread(&r, k, n))
+    }
+
+    /// Find the smallest range of cells around leaf `l` that can absorb
+    /// one more element while staying under its density threshold
+    ///
+    /// Returns `None` if the whole array is too dense and must be doubled.
+    fn window(&self, l: usize) -> Option<Range<usize>> {
+        if self.occupied[l] < self.leaf_size {
+            return Some(self.leaf_boundary(l))
+        }
+
+        let leaves = self.occupied.len();
+        debug_assert!(leaves.is_power_of_two()); // => complete binary tree
+        let height = (2 * leaves).trailing_zeros();
+
+        let tree = conceptual_tree::Tree::new(height);
+        let mut node = tree.get_leaf(l);
+
+        let mut o = self.occupied[l] + 1;
+        let mut c = self.leaf_size;
+        while !node.is_root() {
+            let r = tree.range(node.sibling());
+            c *= 2;
+            o += self.occupied[r].iter().sum::<usize>();
+            node.parent();
+
+            if o as f32 / c as f32 <= upper_threshold(node.depth as f32 / height as f32) {
+                let mut r = tree.range(node);
+                r.start *= self.leaf_size;
+                r.end   *= self.leaf_size;
+                return Some(r)
             }
         }
+        None
     }
 
     fn double(&mut self) {
-        use std::mem;
-
         // TODO doubling strategy?
-        self.leaf_size += 1;
-        let num_leaves = self.occupied.len() * 2;
-        let num_cells = self.leaf_size * num_leaves;
+        let leaf_size = self.leaf_size + 1;
+        let leaves = next_power_of_two(self.occupied.len());
+        self.resize(leaf_size, leaves);
+        count!(self, doubles += 1);
+    }
+
+    /// Undo doublings while the array is sparse enough for the shrink policy
+    fn shrink(&mut self) {
+        let slack = match self.shrink_policy {
+            ShrinkPolicy::Eager => 4,
+            ShrinkPolicy::Lazy => 16,
+            ShrinkPolicy::Never => return,
+        };
+
+        let mut leaf_size = self.leaf_size;
+        let mut leaves = self.occupied.len();
+        while leaves > 2 && self.size * slack <= 1.max(leaf_size - 1) * (leaves / 2) {
+            leaf_size = 1.max(leaf_size - 1);
+            leaves /= 2;
+        }
+
+        if leaves != self.occupied.len() {
+            self.resize(leaf_size, leaves);
+        }
+    }
 
-        self.occupied = vec![0; num_leaves].into_boxed_slice();
+    /// Check the structural invariants, panicking if one is violated
+    #[cfg(test)]
+    fn validate(&self) {
+        let leaves = self.occupied.len();
+        assert!(leaves >= 2 && leaves.is_power_of_two(), "{} leaves is not a power of two", leaves);
+        assert!(self.leaf_size > 0, "Empty leaves");
+        assert_eq!(self.cells.len(), leaves * self.leaf_size, "Cells do not tile the leaves");
+        for l in 0..leaves {
+            let live = self.live_cells(self.leaf_boundary(l));
+            assert_eq!(self.occupied[l], live, "Wrong occupancy of leaf {}", l);
+        }
+        assert_eq!(self.occupied.iter().sum::<usize>(), self.size, "Wrong size");
+    }
+
+    /// Move every element into a new array of `leaves` leaves of `leaf_size` cells
+    fn resize(&mut self, leaf_size: usize, leaves: usize) {
+        use std::mem;
+
+        let num_cells = leaf_size * leaves;
+        self.leaf_size = leaf_size;
+        self.occupied = vec![0; leaves].into_boxed_slice();
         let cells: Vec<_> = mem::replace(&mut self.cells, empty_array(num_cells)).into();
 
         self.redistribute(0..num_cells, cells.into_iter().filter_map(|c| c).collect());
     }
 
+    /// Number of elements stored in the cells of `r`
+    fn live_cells(&self, r: Range<usize>) -> usize {
+        self.cells[r].iter().filter(|c| c.is_some()).count()
+    }
+
+    /// Number of elements stored before cell `i`
+    fn rank_of_cell(&self, i: usize) -> usize {
+        let l = i / self.leaf_size;
+        self.occupied[..l].iter().sum::<usize>() + self.live_cells(l * self.leaf_size..i)
+    }
+
+    /// First element of a non-empty leaf
+    fn leaf_first(&self, l: usize) -> &T {
+        self.cells[self.leaf_boundary(l)].iter().filter_map(|c| c.as_ref()).next().unwrap()
+    }
+
+    /// Cell of the first element not satisfying `pred`, or `self.cells.len()`
+    ///
+    /// `pred` must hold for a prefix of the elements. The leaves are bisected
+    /// by their first element, skipping empty leaves, before scanning the cells
+    /// of a single leaf.
+    fn partition_point<P: FnMut(&T) -> bool>(&self, mut pred: P) -> usize {
+        let (mut lo, mut hi) = (0, self.occupied.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match (mid..hi).find(|&l| self.occupied[l] > 0) {
+                None => hi = mid,
+                Some(l) => if pred(self.leaf_first(l)) { lo = l + 1 } else { hi = l },
+            }
+        }
+
+        // Every non-empty leaf before `hi` begins with an element satisfying
+        // `pred`, so the answer lies within the last of them
+        match (0..hi).rev().find(|&l| self.occupied[l] > 0) {
+            Some(l) => {
+                let r = self.leaf_boundary(l);
+                let end = r.end;
+                r.into_iter()
+                    .find(|&i| self.cells[i].as_ref().map_or(false, |v| !pred(v)))
+                    .unwrap_or(end)
+            }
+            None => 0,
+        }
+    }
+
+    /// First occupied cell at or after `i`, or `self.cells.len()`
+    fn next_live(&self, i: usize) -> usize {
+        let (l, _) = self.leaf(i);
+        let end = self.leaf_boundary(l).end.min(self.cells.len());
+        if let Some(j) = (i..end).find(|&j| self.cells[j].is_some()) {
+            return j
+        }
+        match (l + 1..self.occupied.len()).find(|&l| self.occupied[l] > 0) {
+            Some(l) => self.leaf_boundary(l).find(|&j| self.cells[j].is_some()).unwrap(),
+            None => self.cells.len(),
+        }
+    }
+
+    /// Cell holding the element of rank `k`, or `self.cells.len()` if `k == self.size`
+    fn cell_of_rank(&self, mut k: usize) -> usize {
+        for (l, &o) in self.occupied.iter().enumerate() {
+            if k < o {
+                return self.leaf_boundary(l).filter(|&i| self.cells[i].is_some()).nth(k).unwrap()
+            }
+            k -= o;
+        }
+        self.cells.len()
+    }
+
     /// Returns (index, offset)
     fn leaf(&self, i: usize) -> (usize, usize) {
         let divisor = 1.max(self.leaf_size); // don't divide by zero
@@ -175,28 +642,67 @@ impl<T: Indexable> Ofm<T> {
         r.into_iter().filter_map(|i| self.cell_take(i)).collect()
     }
 
-    fn rebalance(&mut self, r: Range<usize>) {
-        let vs = self.cells_take(r.clone());
-        self.redistribute(r, vs);
-    }
-
+    /// Spread `vs` evenly across `r`
     fn redistribute(&mut self, r: Range<usize>, vs: Vec<T>) {
-        let stride = r.len() / vs.len();
-        let mut i = r.start;
-        for v in vs {
-            self.cell_put(i, v);
-            i += stride;
+        let n = vs.len();
+        for (k, v) in vs.into_iter().enumerate() {
+            self.cell_put(spread(&r, k, n), v);
         }
     }
 }
 
-impl<T> std::ops::Index<Index> for Ofm<T> {
+/// Cell of the `k`th of `n` elements spread evenly across `r`
+fn spread(r: &Range<usize>, k: usize, n: usize) -> usize {
+    debug_assert!(n <= r.len());
+    r.start + k * r.len() / n
+}
+
+impl<T: Indexable> std::ops::Index<Index> for Ofm<T> {
     type Output = T;
     fn index(&self, i: Index) -> &T {
         self.cells[i.0 as usize].as_ref().expect("Invalid index")
     }
 }
 
+/// Compares the sequences of elements, regardless of their layout
+impl<T: Indexable + PartialEq> PartialEq for Ofm<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size && self.leaf_scan().eq(other.leaf_scan())
+    }
+}
+
+impl<T: Indexable + Eq> Eq for Ofm<T> {}
+
+/// Compares the sequences of elements lexicographically
+impl<T: Indexable + PartialOrd> PartialOrd for Ofm<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.leaf_scan().partial_cmp(other.leaf_scan())
+    }
+}
+
+impl<T: Indexable + Ord> Ord for Ofm<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.leaf_scan().cmp(other.leaf_scan())
+    }
+}
+
+/// Shows the cell layout leaf by leaf, e.g. `[1,_,3 | _,5,_]`
+impl<T: Indexable + std::fmt::Debug> std::fmt::Debug for Ofm<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Ofm {{ size: {}, leaf_size: {}, cells: [", self.size, self.leaf_size)?;
+        for (i, cell) in self.cells.iter().enumerate() {
+            if i > 0 {
+                f.write_str(if i % self.leaf_size == 0 { " | " } else { "," })?;
+            }
+            match *cell {
+                Some(ref v) => write!(f, "{:?}", v)?,
+                None => f.write_str("_")?,
+            }
+        }
+        f.write_str("] }")
+    }
+}
+
 pub struct OfmIter<'a, T: 'a> {
     cells: &'a [Option<T>],
     i: usize,
@@ -217,7 +723,7 @@ impl<'a, T> std::iter::Iterator for OfmIter<'a, T> {
 }
 // TODO DoubleSidedIterator, ExactSizeIterator, size_hint
 
-impl<'a, T: 'a> std::iter::IntoIterator for &'a Ofm<T> {
+impl<'a, T: 'a + Indexable> std::iter::IntoIterator for &'a Ofm<T> {
     type Item = &'a T;
     type IntoIter = OfmIter<'a, T>;
     fn into_iter(self) -> Self::IntoIter {
@@ -314,6 +820,7 @@ mod test {
     use super::*;
     use test::Bencher;
     use order::test::N;
+    use util::random_range;
 
     #[test]
     fn test_ofm_back() {
@@ -335,6 +842,418 @@ mod test {
         assert_eq!(o.into_iter().cloned().collect::<Vec<usize>>(), [4usize, 3, 2, 1]);
     }
 
+    #[test]
+    fn test_ofm_insert_after() {
+        let mut o = Ofm::<usize>::new();
+        let one = o.insert(0, 1);
+        o.push_back(5);
+        let two = o.insert_after(one, 2);
+        let three = o.insert_after(two, 3);
+        o.insert_after(three, 4);
+        assert_eq!(o.into_iter().cloned().collect::<Vec<usize>>(), [1usize, 2, 3, 4, 5]);
+
+        // compare against a vector when inserting after random ranks
+        let mut v = o.into_iter().cloned().collect::<Vec<usize>>();
+        for i in 0..1000 {
+            let k = random_range(0, v.len());
+            let cell = o.cell_of_rank(k);
+            o.insert_after(Index(cell), i);
+            v.insert(k + 1, i);
+        }
+        assert_eq!(o.into_iter().cloned().collect::<Vec<usize>>(), v);
+    }
+
+    #[test]
+    fn test_ofm_min_max() {
+        let mut o = Ofm::<usize>::new();
+        assert_eq!(Ofm::min(&o), None);
+        assert_eq!(Ofm::max(&o), None);
+
+        for i in 0..100 {
+            let v = (i * 37) % 100;
+            if i % 2 == 0 { o.push_back(v) } else { o.push_front(v) }
+        }
+        assert_eq!(Ofm::min(&o), Some(&0));
+        assert_eq!(Ofm::max(&o), Some(&99));
+    }
+
+    #[test]
+    fn test_ofm_iter_from() {
+        let mut o = Ofm::<usize>::new();
+        for i in 1..11 { o.push_back(i) }
+
+        let third = Index(o.cell_of_rank(2));
+        assert_eq!(o[third], 3);
+        assert_eq!(o.iter_from(third).cloned().collect::<Vec<usize>>(), [3usize, 4, 5, 6, 7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn test_ofm_count_in_range() {
+        let mut o = Ofm::<usize>::new();
+        for i in 0..1000 { o.push_back(i) }
+
+        assert_eq!(o.count_in_range(250..750), 500);
+        assert_eq!(o.count_in_range(250..=750), 501);
+        assert_eq!(o.count_in_range(..10), 10);
+        assert_eq!(o.count_in_range(990..), 10);
+        assert_eq!(o.count_in_range(..), 1000);
+        assert_eq!(o.count_in_range(2000..3000), 0);
+        assert_eq!(o.count_in_range(500..500), 0);
+    }
+
+    #[test]
+    fn test_ofm_split_at_index() {
+        let mut o = Ofm::<usize>::new();
+        for i in 1..9 { o.push_back(i) }
+
+        let fourth = Index(o.cell_of_rank(3));
+        let mut tail = o.split_at_index(fourth);
+        assert_eq!(o.len(), 3);
+        assert_eq!(tail.len(), 5);
+        assert_eq!(o.into_iter().cloned().collect::<Vec<usize>>(), [1usize, 2, 3]);
+        assert_eq!(tail.into_iter().cloned().collect::<Vec<usize>>(), [4usize, 5, 6, 7, 8]);
+
+        o.push_back(10);
+        tail.push_front(0);
+        assert_eq!(o.into_iter().cloned().collect::<Vec<usize>>(), [1usize, 2, 3, 10]);
+        assert_eq!(tail.into_iter().cloned().collect::<Vec<usize>>(), [0usize, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_ofm_get_many_mut() {
+        let mut o = Ofm::<usize>::new();
+        for i in 0..10 { o.push_back(i) }
+
+        let (a, b, c) = (Index(o.cell_of_rank(1)), Index(o.cell_of_rank(4)), Index(o.cell_of_rank(8)));
+        {
+            let [x, y, z] = o.get_many_mut([a, b, c]).unwrap();
+            *x += 10;
+            *y += 20;
+            *z += 30;
+        }
+        assert_eq!(o.into_iter().cloned().collect::<Vec<usize>>(), [0usize, 11, 2, 3, 24, 5, 6, 7, 38, 9]);
+
+        assert!(o.get_many_mut([a, b, a]).is_none());
+        let empty = (0..o.cells.len()).find(|&i| o.cells[i].is_none()).unwrap();
+        assert!(o.get_many_mut([a, Index(empty)]).is_none());
+    }
+
+    #[test]
+    fn test_ofm_insert_at() {
+        let mut o = Ofm::<char>::new();
+        o.push_back('a');
+        o.push_back('c');
+        let b = o.insert_at(1, 'b');
+        assert_eq!(o[b], 'b');
+        assert_eq!(o.into_iter().cloned().collect::<String>(), "abc");
+
+        o.insert_at(0, '<');
+        o.insert_at(4, '>');
+        let ranks = (0..o.len()).map(|k| o[Index(o.cell_of_rank(k))]).collect::<String>();
+        assert_eq!(ranks, "<abc>");
+    }
+
+    #[test]
+    fn test_ofm_remove() {
+        let mut o = Ofm::<usize>::new();
+        for i in 0..10 { o.push_back(i) }
+
+        let third = Index(o.cell_of_rank(2));
+        assert_eq!(o.remove(third), 2);
+        assert_eq!(o.len(), 9);
+        assert_eq!(o.into_iter().cloned().collect::<Vec<usize>>(), [0usize, 1, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_ofm_checked_index() {
+        let mut o = Ofm::<usize>::with_shrink_policy(ShrinkPolicy::Never);
+        for i in 0..10 { o.push_back(i) }
+
+        let third = Index(o.cell_of_rank(2));
+        assert_eq!(o.checked_index(third), Some(&2));
+        o.remove(third);
+        assert_eq!(o.checked_index(third), None);
+        assert_eq!(o.checked_index(Index(o.capacity())), None);
+    }
+
+    #[test]
+    fn test_ofm_shrink_policy() {
+        fn cycle(policy: ShrinkPolicy) -> (usize, usize, usize) {
+            let mut o = Ofm::with_shrink_policy(policy);
+            for i in 0..1000 { o.push_back(i) }
+            let full = o.capacity();
+
+            while o.len() > 10 {
+                let first = Index(o.cell_of_rank(0));
+                o.remove(first);
+            }
+            let emptied = o.capacity();
+
+            for i in 0..990 { o.push_back(i) }
+            (full, emptied, o.capacity())
+        }
+
+        let (full, emptied, refilled) = cycle(ShrinkPolicy::Never);
+        assert_eq!(emptied, full);
+        assert_eq!(refilled, full);
+
+        let (full, emptied, _) = cycle(ShrinkPolicy::Eager);
+        assert!(emptied * 8 < full);
+
+        let (full, lazy, _) = cycle(ShrinkPolicy::Lazy);
+        assert!(emptied < lazy && lazy < full);
+    }
+
+    #[test]
+    fn test_ofm_debug() {
+        let mut o = Ofm::<usize>::new();
+        assert_eq!(format!("{:?}", o), "Ofm { size: 0, leaf_size: 1, cells: [_ | _] }");
+
+        o.push_back(1);
+        o.push_back(3);
+        o.push_back(5);
+        assert_eq!(format!("{:?}", o), "Ofm { size: 3, leaf_size: 2, cells: [1,_ | _,_ | _,_ | 3,5] }");
+    }
+
+    #[test]
+    fn test_ofm_iter_range() {
+        let mut o = Ofm::<usize>::new();
+        for i in 0..20 { o.push_back(i) }
+
+        assert_eq!(o.iter_range(5..10).cloned().collect::<Vec<usize>>(), [5usize, 6, 7, 8, 9]);
+        assert_eq!(o.iter_range(18..20).cloned().collect::<Vec<usize>>(), [18usize, 19]);
+        assert_eq!(o.iter_range(20..20).count(), 0);
+    }
+
+    #[test]
+    fn test_ofm_binary_search() {
+        let mut o = Ofm::<i32>::new();
+        for i in 0..20 { o.push_back(2 * i) }
+
+        for i in 0..20 {
+            match o.binary_search(&(2 * i)) {
+                Ok(idx) => assert_eq!(o[idx], 2 * i),
+                Err(_) => panic!("{} not found", 2 * i),
+            }
+            match o.binary_search(&(2 * i + 1)) {
+                Err(idx) if i < 19 => assert_eq!(o[idx], 2 * i + 2),
+                Err(idx) => assert_eq!(idx.0, o.capacity()),
+                Ok(_) => panic!("{} found", 2 * i + 1),
+            }
+        }
+
+        match o.binary_search(&-5) {
+            Err(idx) => assert_eq!(o[idx], 0),
+            Ok(_) => panic!("-5 found"),
+        }
+        match o.binary_search(&100) {
+            Err(idx) => assert_eq!(idx.0, o.capacity()),
+            Ok(_) => panic!("100 found"),
+        }
+        assert!(Ofm::<i32>::new().binary_search(&0).is_err());
+    }
+
+    #[test]
+    fn test_ofm_to_vec_refs() {
+        let mut o = Ofm::<usize>::new();
+        for i in 0..10 { o.push_front(i) }
+        let refs = o.to_vec_refs();
+        assert_eq!(refs.len(), 10);
+        assert_eq!(refs.into_iter().cloned().collect::<Vec<_>>(), (0..10).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_ofm_par_iter() {
+        let mut o = Ofm::<u64>::new();
+        for i in 0..100_000 { o.push_back(i) }
+        assert_eq!(o.par_iter().sum::<u64>(), o.into_iter().sum::<u64>());
+    }
+
+    #[test]
+    fn test_ofm_double_boundaries() {
+        use util::random_range;
+
+        let patterns: [fn(&mut Ofm<usize>, &mut Vec<usize>, usize); 4] = [
+            |o, v, x| { o.push_back(x); v.push(x) },
+            |o, v, x| { o.push_front(x); v.insert(0, x) },
+            |o, v, x| { let k = v.len() / 2; o.insert_at(k, x); v.insert(k, x) },
+            |o, v, x| { let k = random_range(0, v.len() + 1); o.insert_at(k, x); v.insert(k, x) },
+        ];
+
+        for insert in &patterns {
+            let mut o = Ofm::with_shrink_policy(ShrinkPolicy::Eager);
+            let mut v = Vec::new();
+            for k in 1..11 {
+                // grow just past the next power of two
+                while v.len() <= 1 << k {
+                    let x = v.len();
+                    insert(&mut o, &mut v, x);
+                    o.validate();
+                }
+                assert_eq!(o.into_iter().cloned().collect::<Vec<_>>(), v);
+
+                // and drop back below it, so the array shrinks and doubles again
+                while v.len() > (1 << k) / 2 {
+                    let first = Index(o.cell_of_rank(0));
+                    assert_eq!(o.remove(first), v.remove(0));
+                    o.validate();
+                }
+            }
+            #[cfg(feature = "stats")]
+            { assert!(o.stats().doubles >= 7); }
+        }
+    }
+
+    #[test]
+    fn test_ofm_extend_ordered() {
+        let mut o = Ofm::<usize>::new();
+        for &x in &[1, 3, 5] { o.push_back(x) }
+
+        o.extend_ordered(vec![2, 4, 6]);
+        o.validate();
+        assert_eq!(o.len(), 6);
+        assert_eq!(o.into_iter().cloned().collect::<Vec<usize>>(), [1usize, 2, 3, 4, 5, 6]);
+
+        o.extend_ordered(0..100);
+        o.validate();
+        let mut expected = (0..100).chain(1..7).collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(o.into_iter().cloned().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn test_ofm_extend_unordered() {
+        let mut o = Ofm::<usize>::new();
+        o.extend_ordered(vec![2, 1]);
+    }
+
+    #[test]
+    fn test_ofm_partition_in_place() {
+        let mut o = Ofm::<usize>::new();
+        for i in 1..7 { o.push_back(i) }
+
+        let i = o.partition_in_place(|&x| x % 2 == 0);
+        o.validate();
+        assert_eq!(o.into_iter().cloned().collect::<Vec<usize>>(), [2usize, 4, 6, 1, 3, 5]);
+        assert_eq!(o[i], 1);
+        assert_eq!(o.iter_from(i).cloned().collect::<Vec<usize>>(), [1usize, 3, 5]);
+
+        let end = o.partition_in_place(|_| true);
+        assert_eq!(end.0, o.capacity());
+    }
+
+    #[test]
+    fn test_ofm_drain_filter() {
+        let mut o = Ofm::<usize>::new();
+        for i in 0..20 { o.push_back(i) }
+
+        let evens = o.drain_filter(|&x| x % 2 == 0);
+        o.validate();
+        assert_eq!(evens, (0..10).map(|x| 2 * x).collect::<Vec<_>>());
+        assert_eq!(o.len(), 10);
+        assert_eq!(o.into_iter().cloned().collect::<Vec<_>>(), (0..10).map(|x| 2 * x + 1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_ofm_ord() {
+        use std::cmp::Ordering;
+
+        fn ofm(vs: &[usize]) -> Ofm<usize> {
+            let mut o = Ofm::new();
+            for &v in vs { o.push_back(v) }
+            o
+        }
+
+        assert_eq!(ofm(&[1, 2]).cmp(&ofm(&[1, 2, 3])), Ordering::Less);
+        assert_eq!(ofm(&[1, 3]).cmp(&ofm(&[1, 2, 9])), Ordering::Greater);
+        assert!(ofm(&[]) < ofm(&[0]));
+
+        // equal despite different layouts
+        let mut a = ofm(&[2, 3]);
+        a.push_front(1);
+        let b = Ofm::from_vec(vec![1, 2, 3]);
+        assert!(a.cells != b.cells);
+        assert_eq!(a.cmp(&b), Ordering::Equal);
+        assert!(a == b);
+        assert!(a != ofm(&[1, 2]));
+    }
+
+    #[test]
+    fn test_ofm_nth_from_end() {
+        let mut o = Ofm::<usize>::new();
+        for i in 0..10 { o.push_back(i) }
+
+        assert_eq!(o.nth_from_end(0), Some(&9));
+        assert_eq!(o.nth_from_end(3), Some(&6));
+        assert_eq!(o.nth_from_end(9), Some(&0));
+        assert_eq!(o.nth_from_end(10), None);
+    }
+
+    #[test]
+    fn test_ofm_binary_search_borrow() {
+        let mut o = Ofm::<String>::new();
+        for w in &["apple", "banana", "fig", "kiwi", "pear"] {
+            o.push_back(w.to_string());
+        }
+
+        match o.binary_search_borrow("fig") {
+            Ok(i) => assert_eq!(o[i], "fig"),
+            Err(_) => panic!("fig not found"),
+        }
+        match o.binary_search_borrow("grape") {
+            Err(i) => assert_eq!(o[i], "kiwi"),
+            Ok(_) => panic!("grape found"),
+        }
+        match o.binary_search_borrow("zucchini") {
+            Err(i) => assert_eq!(i.0, o.capacity()),
+            Ok(_) => panic!("zucchini found"),
+        }
+    }
+
+    #[test]
+    fn test_ofm_as_contiguous() {
+        let mut o = Ofm::<usize>::new();
+        for i in 0..50 {
+            if i % 3 == 0 { o.push_front(i) } else { o.push_back(i) }
+        }
+
+        let (values, indices) = o.as_contiguous();
+        assert_eq!(values.len(), 50);
+        assert_eq!(indices.len(), 50);
+        for k in 0..50 {
+            assert_eq!(&o[indices[k]], values[k]);
+        }
+        assert_eq!(values, o.to_vec_refs());
+    }
+
+    #[test]
+    fn test_ofm_density() {
+        // insert through `f`, checking the array only doubles once the root is too dense
+        fn check<F: Fn(&mut Ofm<usize>, usize)>(f: F) {
+            let mut o = Ofm::<usize>::new();
+            for i in 0..1000 {
+                let cells = o.cells.len();
+                f(&mut o, i);
+                if o.cells.len() != cells {
+                    assert!(o.size as f32 > upper_threshold(0.0) * cells as f32, "Doubled too early");
+                }
+                assert!(4 * o.size >= o.cells.len() || o.size < 4, "Array too sparse");
+                assert!(o.occupied.iter().all(|&n| n <= o.leaf_size), "Leaf overflowed");
+            }
+            assert_eq!(o.into_iter().count(), 1000);
+        }
+
+        // the same leaf keeps filling up at either end
+        check(|o, i| if i % 2 == 0 { o.push_front(i) } else { o.push_back(i) });
+        check(|o, i| {
+            let mid = o.cell_of_rank(o.size / 2);
+            o.insert(mid, i);
+        });
+    }
+
     #[test]
     fn test_ofm_moving() {
         struct Atom(usize);
@@ -352,6 +1271,26 @@ mod test {
         assert!(o.into_iter().map(|a| a.0).all(|n| n > 0))
     }
 
+    #[test]
+    fn test_ofm_reserve() {
+        let mut o = Ofm::<usize>::new();
+        for i in 0..100 { o.push_back(i) }
+
+        o.reserve(1000);
+        let capacity = o.capacity();
+        #[cfg(feature = "stats")]
+        let stats = o.stats();
+        for i in 100..600 { o.push_back(i) }
+        for i in 0..500 { o.push_front(i) }
+
+        assert_eq!(o.capacity(), capacity);
+        #[cfg(feature = "stats")]
+        { assert_eq!(o.stats().doubles, stats.doubles); }
+        assert_eq!(o.len(), 1100);
+        assert!(o.into_iter().take(500).cloned().eq((0..500).rev()));
+        assert!(o.into_iter().skip(500).cloned().eq(0..600));
+    }
+
     #[bench]
     fn bench_ofm_push_back(b: &mut Bencher) {
         b.iter(|| {
@@ -368,6 +1307,19 @@ mod test {
         })
     }
 
+    #[bench]
+    fn bench_ofm_insert_middle(b: &mut Bencher) {
+        b.iter(|| {
+            let mut o = Ofm::new();
+            o.push_back(0);
+            o.push_back(0);
+            for i in 0..N {
+                let mid = o.len() / 2;
+                o.insert_at(mid, i);
+            }
+        })
+    }
+
     #[bench]
     fn bench_ofm_iter(b: &mut Bencher) {
         let mut o = Ofm::new();
@@ -1,16 +1,56 @@
-use util::random_samples;
+use std::mem;
+use std::sync::Once;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use test::black_box;
+use util::{random_range, random_samples};
+
 const M: usize = 4096;
 const B: usize = 64;
-const sMB: usize = 8; // sqrt(M/B)
 
-fn external_distribution_sort<T: Clone+Ord>(array: &[T]) -> Vec<T> {
-    if array.len() <= M {
+/// Cache sizes (in bytes) considered by the probe, smallest first
+const CACHE_SIZES: [usize; 5] = [16 << 10, 32 << 10, 64 << 10, 128 << 10, 256 << 10];
+const CACHE_LINE: usize = 64;
+
+static PROBE: Once = Once::new();
+static CACHE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Sort using the default parameters `M = 4096` and `B = 64`
+pub fn external_distribution_sort<T: Clone+Ord>(array: &[T]) -> Vec<T> {
+    external_distribution_sort_mb(array, M, B)
+}
+
+/// Sort with a cache of `m` elements split into blocks of `b` elements
+///
+/// Inputs of at most `m` elements are sorted directly, larger inputs are
+/// distributed around `sqrt(m/b)` random pivots.
+pub fn external_distribution_sort_mb<T: Clone+Ord>(array: &[T], m: usize, b: usize) -> Vec<T> {
+    debug_assert!(b > 0 && m >= b);
+    let pivots = ((m / b) as f64).sqrt() as usize;
+    distribute(array, m, pivots.max(1))
+}
+
+/// Sort with `M` and `B` chosen from the cache size of this machine
+///
+/// The cache size is probed once per process, see `cache_size()`.
+pub fn external_distribution_sort_auto<T: Clone+Ord>(array: &[T]) -> Vec<T> {
+    let size = mem::size_of::<T>().max(1);
+    let b = (CACHE_LINE / size).max(1);
+    let m = (cache_size() / size).max(b);
+    external_distribution_sort_mb(array, m, b)
+}
+
+fn distribute<T: Clone+Ord>(array: &[T], m: usize, num_pivots: usize) -> Vec<T> {
+    if array.len() <= m {
         let mut array = array.to_vec();
         array.sort_unstable();
         return array
     }
 
-    let mut pivots = random_samples(array, sMB);
+    let mut pivots = random_samples(array, num_pivots);
     pivots.sort_unstable();
 
     let mut partitions = (0..pivots.len() + 1).map(|_| Vec::new()).collect::<Vec<_>>();
@@ -18,14 +58,119 @@ fn external_distribution_sort<T: Clone+Ord>(array: &[T]) -> Vec<T> {
         let pnum = pivots.iter().filter(|&x| x < ele).count();
         partitions[pnum].push(ele.clone());
     }
+    if let Some(sorted) = sort_unsplit(&pivots, &mut partitions, array.len()) {
+        return sorted
+    }
 
     let mut output = Vec::with_capacity(array.len());
     for p in partitions {
-        output.extend(external_distribution_sort(&*p))
+        output.extend(distribute(&*p, m, num_pivots))
     }
     output
 }
 
+/// Sort using the default parameters, assigning elements to partitions and
+/// sorting the partitions in parallel
+#[cfg(feature = "rayon")]
+pub fn par_external_distribution_sort<T: Clone+Ord+Send+Sync>(array: &[T]) -> Vec<T> {
+    let pivots = ((M / B) as f64).sqrt() as usize;
+    par_distribute(array, M, pivots.max(1))
+}
+
+#[cfg(feature = "rayon")]
+fn par_distribute<T: Clone+Ord+Send+Sync>(array: &[T], m: usize, num_pivots: usize) -> Vec<T> {
+    if array.len() <= m {
+        return distribute(array, m, num_pivots)
+    }
+
+    let mut pivots = random_samples(array, num_pivots);
+    pivots.sort_unstable();
+
+    let pnums = array.par_iter()
+        .map(|ele| pivots.iter().filter(|&x| x < ele).count())
+        .collect::<Vec<usize>>();
+    let mut partitions = (0..pivots.len() + 1).map(|_| Vec::new()).collect::<Vec<_>>();
+    for (ele, &pnum) in array.iter().zip(&pnums) {
+        partitions[pnum].push(ele.clone());
+    }
+    if let Some(sorted) = sort_unsplit(&pivots, &mut partitions, array.len()) {
+        return sorted
+    }
+
+    let mut sorted = partitions.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+    rayon::scope(|s| {
+        for (p, out) in partitions.iter().zip(&mut sorted) {
+            s.spawn(move |_| *out = par_distribute(p, m, num_pivots));
+        }
+    });
+
+    let mut output = Vec::with_capacity(array.len());
+    for p in sorted {
+        output.extend(p)
+    }
+    output
+}
+
+/// Sort the partition holding all `len` elements, if there is one
+///
+/// This happens when every pivot is equal, and recursing would only produce
+/// the same partition again, e.g. for more than `m` copies of one element.
+fn sort_unsplit<T: Ord>(pivots: &[T], partitions: &mut [Vec<T>], len: usize) -> Option<Vec<T>> {
+    if pivots.first() != pivots.last() {
+        return None
+    }
+    partitions.iter_mut().find(|p| p.len() == len).map(|p| {
+        p.sort_unstable();
+        mem::replace(p, Vec::new())
+    })
+}
+
+/// Size in bytes of the fastest cache level, as measured by `probe_cache_size()`
+pub fn cache_size() -> usize {
+    PROBE.call_once(|| CACHE_SIZE.store(probe_cache_size(), Ordering::Relaxed));
+    CACHE_SIZE.load(Ordering::Relaxed)
+}
+
+/// Pick the largest of `CACHE_SIZES` whose access latency is at most twice
+/// that of the smallest
+fn probe_cache_size() -> usize {
+    let base = chase_latency(CACHE_SIZES[0]);
+    let mut best = CACHE_SIZES[0];
+    for &bytes in &CACHE_SIZES[1..] {
+        if chase_latency(bytes) > 2.0 * base {
+            break
+        }
+        best = bytes;
+    }
+    best
+}
+
+/// Average nanoseconds per step when chasing pointers through `bytes` of memory
+///
+/// The pointers form a single random cycle (Sattolo's algorithm) so the
+/// hardware prefetcher cannot hide the latency.
+fn chase_latency(bytes: usize) -> f64 {
+    const STEPS: u32 = 1 << 20;
+
+    let n = bytes / mem::size_of::<usize>();
+    let mut next = (0..n).collect::<Vec<usize>>();
+    for i in (1..n).rev() {
+        let j = random_range(0, i);
+        next.swap(i, j);
+    }
+
+    let mut i = 0;
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        i = next[i];
+    }
+    black_box(i);
+
+    let elapsed = start.elapsed();
+    let ns = elapsed.as_secs() as f64 * 1e9 + elapsed.subsec_nanos() as f64;
+    ns / STEPS as f64
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -43,15 +188,54 @@ mod test {
         assert!(l == dsorted);
     }
 
+    #[test]
+    fn correct_auto() {
+        let mut l = generate_array_medium();
+
+        let dsorted = external_distribution_sort_auto(&*l);
+        l.sort_unstable();
+
+        assert!(CACHE_SIZES.contains(&cache_size()));
+        assert!(l == dsorted);
+    }
+
+    #[test]
+    fn correct_repeated() {
+        let l = vec![7; M + 100];
+        assert!(external_distribution_sort(&*l) == l);
+
+        let mut l = vec![5; M + 100];
+        l.extend(&[9, 1, 5, 3, 8, 0, 5, 2]);
+        let dsorted = external_distribution_sort(&*l);
+        l.sort_unstable();
+        assert!(l == dsorted);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn correct_par() {
+        let mut l = generate_array_large();
+
+        let dsorted = par_external_distribution_sort(&*l);
+        l.sort_unstable();
+
+        assert!(l == dsorted);
+    }
+
     macro_rules! bench {
-        ($name:ident, $array:expr) => {
+        ($name:ident, $sort:ident, $array:expr) => {
             #[bench] fn $name (b: &mut Bencher) {
-                b.iter(|| external_distribution_sort(&*$array));
+                b.iter(|| $sort(&*$array));
             }
         }
     }
 
-    bench!(bench_s, generate_array_small());
-    bench!(bench_m, generate_array_medium());
-    bench!(bench_l, generate_array_large());
+    bench!(bench_s, external_distribution_sort, generate_array_small());
+    bench!(bench_m, external_distribution_sort, generate_array_medium());
+    bench!(bench_l, external_distribution_sort, generate_array_large());
+    bench!(bench_auto_s, external_distribution_sort_auto, generate_array_small());
+    bench!(bench_auto_m, external_distribution_sort_auto, generate_array_medium());
+    bench!(bench_auto_l, external_distribution_sort_auto, generate_array_large());
+    #[cfg(feature = "rayon")]
+    bench!(bench_par_distribution_l, par_external_distribution_sort, generate_array_large());
 }
@@ -0,0 +1,61 @@
+//! Comparators over several keys
+//!
+//! ```ignore
+//! let chain = KeyChain::new().then_by_key(|x: &Rec| x.a).then_by_key_desc(|x: &Rec| x.b);
+//! quick_sort_by(&mut records, |x, y| chain.compare(x, y));
+//! ```
+
+use std::cmp::Ordering;
+
+/// Compares by each key in turn, moving to the next key on ties
+pub struct KeyChain<'a, T> {
+    keys: Vec<Box<dyn Fn(&T, &T) -> Ordering + 'a>>,
+}
+
+impl<'a, T> KeyChain<'a, T> {
+    /// A chain without keys, which considers everything equal
+    pub fn new() -> Self {
+        KeyChain { keys: Vec::new() }
+    }
+
+    /// Break ties by `key`, ascending
+    pub fn then_by_key<K: Ord, F: Fn(&T) -> K + 'a>(mut self, key: F) -> Self {
+        self.keys.push(Box::new(move |a, b| key(a).cmp(&key(b))));
+        self
+    }
+
+    /// Break ties by `key`, descending
+    pub fn then_by_key_desc<K: Ord, F: Fn(&T) -> K + 'a>(mut self, key: F) -> Self {
+        self.keys.push(Box::new(move |a, b| key(b).cmp(&key(a))));
+        self
+    }
+
+    /// Compare by the first key on which `a` and `b` differ
+    pub fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.keys.iter()
+            .map(|k| k(a, b))
+            .find(|&o| o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sort::quick::quick_sort_by;
+
+    #[test]
+    fn correct() {
+        // (department, salary, id)
+        let mut records = [(2, 50, 0), (1, 30, 1), (2, 70, 2), (1, 90, 3), (1, 30, 4), (3, 10, 5)];
+
+        let chain = KeyChain::new()
+            .then_by_key(|r: &(u32, u32, u32)| r.0)
+            .then_by_key_desc(|r: &(u32, u32, u32)| r.1);
+        quick_sort_by(&mut records, |a, b| chain.compare(a, b));
+
+        let order = records.iter().map(|r| (r.0, r.1)).collect::<Vec<_>>();
+        assert_eq!(order, [(1, 90), (1, 30), (1, 30), (2, 70), (2, 50), (3, 10)]);
+        assert_eq!(KeyChain::<u32>::new().compare(&1, &2), Ordering::Equal);
+    }
+}
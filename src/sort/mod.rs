@@ -1,4 +1,5 @@
 pub mod distribution;
+pub mod key_chain;
 pub mod quick;
 
 #[cfg(test)]
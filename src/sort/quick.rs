@@ -1,19 +1,68 @@
 //! Hoare's Quick Sort
 
+use std::cmp::Ordering;
+
+use util::random_range;
+
 pub fn quick_sort<T: Copy+Ord>(array: &mut [T]) {
+    quick_sort_by(array, |a, b| a.cmp(b))
+}
+
+pub fn quick_sort_by<T: Copy, F: FnMut(&T, &T) -> Ordering>(array: &mut [T], mut compare: F) {
+    let len = array.len();
+    if len == 0 { return }
+    quick_sort_range_by(array, 0, len - 1, &mut compare, false)
+}
+
+/// Sort using a uniformly random pivot
+///
+/// Takes expected `O(n log n)` time on every input, including sorted input
+/// which is quadratic for `quick_sort()`.
+pub fn quick_sort_randomized<T: Copy+Ord>(array: &mut [T]) {
     let len = array.len();
-    quick_sort_range(array, 0, len - 1)
+    if len == 0 { return }
+    quick_sort_range_by(array, 0, len - 1, &mut |a: &T, b: &T| a.cmp(b), true)
+}
+
+/// Sort references to the elements of `slice`, leaving `slice` untouched
+///
+/// Cheaper than sorting `slice` itself when its elements are large.
+pub fn quick_sort_refs<'a, T: Ord>(slice: &'a [T]) -> Vec<&'a T> {
+    let mut refs = slice.iter().collect::<Vec<_>>();
+    quick_sort(&mut *refs);
+    refs
+}
+
+/// Sort floats by `f64::total_cmp`
+///
+/// Positive NaNs (such as `f64::NAN`) sort after infinity, negative NaNs
+/// before negative infinity, and `-0.0` sorts before `0.0`.
+pub fn quick_sort_f64(array: &mut [f64]) {
+    quick_sort_by(array, |a, b| a.total_cmp(b))
 }
 
 pub fn quick_sort_range<T: Copy+Ord>(array: &mut [T], lo: usize, hi: usize) {
+    quick_sort_range_by(array, lo, hi, &mut |a: &T, b: &T| a.cmp(b), false)
+}
+
+fn quick_sort_range_by<T, F>(array: &mut [T], lo: usize, hi: usize,
+                             compare: &mut F, random_pivot: bool)
+    where T: Copy, F: FnMut(&T, &T) -> Ordering
+{
     if lo < hi {
-        let p = partition(array, lo   , hi);
-        quick_sort_range (array, lo   , p );
-        quick_sort_range (array, p + 1, hi);
+        let p = partition(array, lo   , hi, compare, random_pivot);
+        quick_sort_range_by (array, lo   , p , compare, random_pivot);
+        quick_sort_range_by (array, p + 1, hi, compare, random_pivot);
     }
 }
 
-fn partition<T: Copy+Ord>(array: &mut [T], lo: usize, hi: usize) -> usize {
+fn partition<T, F>(array: &mut [T], lo: usize, hi: usize,
+                   compare: &mut F, random_pivot: bool) -> usize
+    where T: Copy, F: FnMut(&T, &T) -> Ordering
+{
+    if random_pivot {
+        array.swap(lo, random_range(lo, hi + 1));
+    }
     let pivot = array[lo];
     let mut i = lo.wrapping_sub(1);
     let mut j = hi.wrapping_add(1);
@@ -21,8 +70,8 @@ fn partition<T: Copy+Ord>(array: &mut [T], lo: usize, hi: usize) -> usize {
         i = i.wrapping_add(1);
         j = j.wrapping_sub(1);
 
-        while array[i] < pivot { i = i.wrapping_add(1) }
-        while array[j] > pivot { j = j.wrapping_sub(1) }
+        while compare(&array[i], &pivot) == Ordering::Less    { i = i.wrapping_add(1) }
+        while compare(&array[j], &pivot) == Ordering::Greater { j = j.wrapping_sub(1) }
 
         if i >= j { return j }
         array.swap(i, j);
@@ -34,6 +83,7 @@ mod test {
     use super::*;
     use test::Bencher;
     use sort::test::*;
+    use util::{sorted_array, reverse_array, organ_pipe, sawtooth};
 
     #[test]
     fn correct() {
@@ -47,10 +97,59 @@ mod test {
         assert!(l == qsorted);
     }
 
+    #[test]
+    fn correct_refs() {
+        let words = ["pear", "apple", "fig", "kiwi", "banana"];
+        let l = words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+
+        let refs = quick_sort_refs(&*l);
+        assert_eq!(refs, ["apple", "banana", "fig", "kiwi", "pear"]);
+        assert_eq!(l, words);
+        assert!(quick_sort_refs::<String>(&[]).is_empty());
+    }
+
+    #[test]
+    fn correct_f64() {
+        let mut l = [f64::NAN, 1.5, f64::NEG_INFINITY, 0.0, -f64::NAN, -0.0, f64::INFINITY, -1.5, f64::NAN];
+        quick_sort_f64(&mut l);
+
+        assert!(l[0].is_nan() && l[0].is_sign_negative());
+        assert_eq!(l[1..7], [f64::NEG_INFINITY, -1.5, 0.0, 0.0, 1.5, f64::INFINITY]);
+        assert!(l[3].is_sign_negative() && l[4].is_sign_positive());
+        assert!(l[7..].iter().all(|x| x.is_nan() && x.is_sign_positive()));
+    }
+
+    #[test]
+    fn correct_randomized() {
+        use util::random_array;
+        let mut l = random_array(1024);
+
+        let mut qsorted = l.clone();
+        quick_sort_randomized(&mut *qsorted);
+
+        l.sort_unstable();
+        assert!(l == qsorted);
+    }
+
+    #[test]
+    fn randomized_sorted() {
+        // quadratic for quick_sort(), recursing once per element
+        let mut l = sorted_array(1 << 17);
+        quick_sort_randomized(&mut *l);
+        assert!(l == sorted_array(1 << 17));
+
+        let mut l = reverse_array(1 << 17);
+        quick_sort_randomized(&mut *l);
+        assert!(l == sorted_array(1 << 17));
+    }
+
     macro_rules! bench {
         ($name:ident, $array:expr) => {
+            bench!($name, quick_sort, $array);
+        };
+        ($name:ident, $sort:ident, $array:expr) => {
             #[bench] fn $name (b: &mut Bencher) {
-                b.iter(|| quick_sort(&mut *$array));
+                b.iter(|| $sort(&mut *$array));
             }
         }
     }
@@ -58,4 +157,12 @@ mod test {
     bench!(bench_s, generate_array_small());
     bench!(bench_m, generate_array_medium());
     bench!(bench_l, generate_array_large());
+
+    // The first element is the pivot, so these are quadratic; keep them small
+    bench!(bench_sorted_s, sorted_array(1024));
+    bench!(bench_reverse_s, reverse_array(1024));
+    bench!(bench_organ_pipe_s, organ_pipe(1024));
+    bench!(bench_sawtooth_s, sawtooth(1024, 32));
+    bench!(bench_randomized_m, quick_sort_randomized, generate_array_medium());
+    bench!(bench_randomized_sorted_m, quick_sort_randomized, sorted_array(64 * 1024));
 }
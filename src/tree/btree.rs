@@ -1,6 +1,8 @@
 //! Maximum number of children below a node
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
 
 pub const B: usize = 7;
 /* const */ fn midpoint() -> usize {
@@ -8,6 +10,7 @@ pub const B: usize = 7;
     // cut in half rounding down.
     // This provides a zero-based index into the middle of an array of length B
     // E.g. if B=7, then B/2 = 3 which is middle index of an array of 7
+    // It is also the fewest items a node other than the root may hold.
     B / 2
 }
 
@@ -16,13 +19,18 @@ fn make_ptr<K: Ord, V>(node: BTreeNode<K, V>) -> BTreePtr<K, V> {
     Rc::new(RefCell::new(node))
 }
 
+/// Nodes passed on the way down from the root, with the index of the child taken
+type Path<K, V> = Vec<(BTreePtr<K, V>, usize)>;
+
+/// An item found by its index in a node, with the path to that node
+type Found<K, V> = (Path<K, V>, BTreePtr<K, V>, usize);
+
 pub struct BTree<K, V> where K: Ord {
     root: BTreePtr<K, V>,
     size: usize,
 }
 
 pub struct BTreeNode<K, V> where K: Ord {
-    parent: Option<BTreePtr<K, V>>,
     children: Vec<BTreePtr<K, V>>,
     items: Vec<(K, V)>,
 }
@@ -35,46 +43,346 @@ impl<K: Ord, V> BTree<K, V> {
         }
     }
 
-    pub fn insert(&mut self, k: K, v: V) {
-        let node = self.find_insert(k);
-        node.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Insert `v` with key `k`, returning the value it replaces
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        let mut path = Vec::new();
+        let mut node = self.root.clone();
+        loop {
+            let found = node.borrow().search(&k);
+            let i = match found {
+                Ok(i) => return Some(mem::replace(&mut node.borrow_mut().items[i].1, v)),
+                Err(i) => i,
+            };
+            if node.borrow().leaf() {
+                node.borrow_mut().items.insert(i, (k, v));
+                break
+            }
+            let child = node.borrow().children[i].clone();
+            path.push((node, i));
+            node = child;
+        }
+
+        self.size += 1;
+        self.balance(node, path);
+        None
+    }
+
+    pub fn contains_key(&self, k: &K) -> bool {
+        let mut node = self.root.clone();
+        loop {
+            let next = match node.borrow().search(k) {
+                Ok(_) => return true,
+                Err(_) if node.borrow().leaf() => return false,
+                Err(i) => node.borrow().children[i].clone(),
+            };
+            node = next;
+        }
+    }
+
+    /// Remove `k`, returning its value
+    pub fn remove(&mut self, k: &K) -> Option<V> {
+        let (path, node, i) = self.lower_bound(|key| key < k)?;
+        if node.borrow().items[i].0 != *k {
+            return None
+        }
+        self.size -= 1;
+        Some(self.remove_at(path, node, i).1)
+    }
+
+    /// Remove the item with the smallest key
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let (path, node, i) = self.lower_bound(|_| false)?;
+        self.size -= 1;
+        Some(self.remove_at(path, node, i))
+    }
+
+    /// Remove every item with a key in `range`
+    ///
+    /// The range is split off as a whole and dropped, and the pieces on either
+    /// side are joined, rather than removing the keys one at a time.
+    pub fn remove_range<R: RangeBounds<K>>(&mut self, range: R) {
+        let mut middle = self.split_off_by(|k| match range.start_bound() {
+            Bound::Included(start) => k < start,
+            Bound::Excluded(start) => k <= start,
+            Bound::Unbounded => false,
+        });
+        let right = middle.split_off_by(|k| match range.end_bound() {
+            Bound::Included(end) => k <= end,
+            Bound::Excluded(end) => k < end,
+            Bound::Unbounded => true,
+        });
+
+        let removed = middle.root.borrow().count();
+        self.join(right);
+        self.size -= removed;
+    }
+
+    /// Move the items not `before` the split point to a new tree
+    ///
+    /// Every node on the path of the split is cut in two, so both trees keep
+    /// the same height, and the nodes left underfull along the cut are then
+    /// refilled. Sizes are left to the caller.
+    fn split_off_by<F: Fn(&K) -> bool>(&mut self, before: F) -> Self {
+        let mut right = BTree::new();
+        let (mut l, mut r) = (self.root.clone(), right.root.clone());
+        loop {
+            let i = l.borrow().items.iter().take_while(|kv| before(&kv.0)).count();
+            {
+                let (mut ln, mut rn) = (l.borrow_mut(), r.borrow_mut());
+                rn.items = ln.items.split_off(i);
+                if ln.leaf() {
+                    break
+                }
+                rn.children = ln.children.split_off(i + 1);
+                rn.children.insert(0, make_ptr(BTreeNode::new_root()));
+            }
+            let (next_l, next_r) = (l.borrow().children[i].clone(), r.borrow().children[0].clone());
+            l = next_l;
+            r = next_r;
+        }
+
+        self.fix_border(true);
+        right.fix_border(false);
+        right
     }
 
-    fn find_insert(&self, k: K) -> &BTreeNode<K, V> {
-        if self.root.borrow().items.len() == 0 {
-            self.root
+    /// Append `right`, whose keys must all be greater than those of `self`
+    ///
+    /// The smallest item of `right` separates the two trees, and the shorter
+    /// tree is hung from the border of the taller one. Sizes are left to the
+    /// caller.
+    fn join(&mut self, mut right: Self) {
+        let sep = match right.lower_bound(|_| false) {
+            Some((path, node, i)) => right.remove_at(path, node, i),
+            None => return,
+        };
+
+        let (hl, hr) = (self.height(), right.height());
+        if hl == hr {
+            self.root = make_ptr(BTreeNode {
+                children: vec![self.root.clone(), right.root],
+                items: vec![sep],
+            });
+            // both old roots may be underfull
+            while !self.root.borrow().items.is_empty() {
+                let under = self.root.borrow().children.iter()
+                    .position(|c| c.borrow().items.len() < midpoint());
+                match under {
+                    Some(i) => self.root.borrow_mut().refill(i),
+                    None => break,
+                }
+            }
+            self.fix_root();
+        } else if hl > hr {
+            // the node on the right border of `self` one level above `right`
+            let mut path = Vec::new();
+            let mut node = self.root.clone();
+            for _ in 0..hl - hr - 1 {
+                let i = node.borrow().items.len();
+                let child = node.borrow().children[i].clone();
+                path.push((node, i));
+                node = child;
+            }
+            node.borrow_mut().items.push(sep);
+            node.borrow_mut().children.push(right.root);
+            loop {
+                let i = node.borrow().items.len();
+                let under = node.borrow().children[i].borrow().items.len() < midpoint();
+                if !under {
+                    break
+                }
+                node.borrow_mut().refill(i);
+            }
+            self.balance(node, path);
         } else {
+            // the node on the left border of `right` one level above `self`
+            let mut path = Vec::new();
+            let mut node = right.root.clone();
+            for _ in 0..hr - hl - 1 {
+                let child = node.borrow().children[0].clone();
+                path.push((node, 0));
+                node = child;
+            }
+            node.borrow_mut().items.insert(0, sep);
+            node.borrow_mut().children.insert(0, self.root.clone());
+            while node.borrow().children[0].borrow().items.len() < midpoint() {
+                node.borrow_mut().refill(0);
+            }
+            self.root = right.root;
+            self.balance(node, path);
+        }
+    }
+
+    /// The first item not `before` some point, with its node and the
+    /// ancestors of that node
+    fn lower_bound<F: Fn(&K) -> bool>(&self, before: F) -> Option<Found<K, V>> {
+        let mut path = Vec::new();
+        let mut found = None;
+        let mut node = self.root.clone();
+        loop {
+            let (i, len, leaf) = {
+                let n = node.borrow();
+                (n.items.iter().take_while(|kv| before(&kv.0)).count(), n.items.len(), n.leaf())
+            };
+            if i < len {
+                found = Some((path.len(), node.clone(), i));
+            }
+            if leaf {
+                break
+            }
+            let child = node.borrow().children[i].clone();
+            path.push((node, i));
+            node = child;
+        }
+
+        found.map(|(depth, node, i)| {
+            path.truncate(depth);
+            (path, node, i)
+        })
+    }
+
+    /// Remove the `i`th item of `node`, whose ancestors are `path`
+    ///
+    /// An item of an internal node is replaced by its predecessor, which is
+    /// removed from its leaf instead.
+    fn remove_at(&mut self, mut path: Path<K, V>, node: BTreePtr<K, V>, i: usize) -> (K, V) {
+        if node.borrow().leaf() {
+            let kv = node.borrow_mut().items.remove(i);
+            self.rebalance(node, path);
+            return kv
+        }
+
+        let mut leaf = node.borrow().children[i].clone();
+        path.push((node.clone(), i));
+        while !leaf.borrow().leaf() {
+            let last = leaf.borrow().items.len();
+            let child = leaf.borrow().children[last].clone();
+            path.push((leaf, last));
+            leaf = child;
+        }
+        let pred = leaf.borrow_mut().items.pop().unwrap();
+        let kv = mem::replace(&mut node.borrow_mut().items[i], pred);
+        self.rebalance(leaf, path);
+        kv
+    }
+
+    /// Split `node`, and then its ancestors in `path`, while they overflow
+    fn balance(&mut self, mut node: BTreePtr<K, V>, mut path: Path<K, V>) {
+        while node.borrow().items.len() == B {
+            let (midpoint, right) = node.borrow_mut().split();
+            match path.pop() {
+                Some((parent, i)) => {
+                    parent.borrow_mut().upinsert(i, midpoint, right);
+                    node = parent;
+                }
+                None => {
+                    // create new root
+                    let mut root = BTreeNode::new_root();
+                    root.items.push(midpoint);
+                    root.children.push(node);
+                    root.children.push(right);
+                    self.root = make_ptr(root);
+                    return
+                }
+            }
+        }
+    }
+
+    /// Refill `node`, and then its ancestors in `path`, while they underflow
+    fn rebalance(&mut self, mut node: BTreePtr<K, V>, mut path: Path<K, V>) {
+        while let Some((parent, i)) = path.pop() {
+            if node.borrow().items.len() >= midpoint() {
+                break
+            }
+            parent.borrow_mut().refill(i);
+            node = parent;
+        }
+        self.fix_root();
+    }
+
+    /// Refill the underfull nodes along the right (or left) border, which a
+    /// split leaves behind
+    fn fix_border(&mut self, right: bool) {
+        loop {
+            self.fix_root();
+            let mut path = Vec::new();
+            let mut node = self.root.clone();
+            let underfull = loop {
+                if node.borrow().leaf() {
+                    break false
+                }
+                let i = if right { node.borrow().items.len() } else { 0 };
+                let child = node.borrow().children[i].clone();
+                path.push((node, i));
+                if child.borrow().items.len() < midpoint() {
+                    break true
+                }
+                node = child;
+            };
+            if !underfull {
+                return
+            }
+
+            let (parent, i) = path.pop().unwrap();
+            parent.borrow_mut().refill(i);
+            self.rebalance(parent, path);
+        }
+    }
+
+    /// Replace the root by its only child while it has no items
+    fn fix_root(&mut self) {
+        while self.root.borrow().items.is_empty() && !self.root.borrow().leaf() {
+            let child = self.root.borrow().children[0].clone();
+            self.root = child;
         }
     }
 
-    fn print(&self) {
-        // self.root.borrow().print(0);
+    fn height(&self) -> usize {
+        let mut height = 1;
+        let mut node = self.root.clone();
+        while !node.borrow().leaf() {
+            let child = node.borrow().children[0].clone();
+            node = child;
+            height += 1;
+        }
+        height
+    }
+
+    /// Check the structural invariants, panicking if one is violated
+    #[cfg(test)]
+    fn validate(&self) {
+        let (_, count) = self.root.borrow().validate(true, None, None);
+        assert_eq!(count, self.size);
     }
 }
 
 impl<K: Ord, V> BTreeNode<K, V> {
     fn new_root() -> Self {
         BTreeNode {
-            parent: None,
             children: Vec::with_capacity(B),
             items: Vec::with_capacity(B),
         }
     }
 
-    fn new(parent: &BTreePtr<K, V>) -> Self {
-        BTreeNode {
-            parent: Some(parent.clone() /* clone RC ptr */),
-            children: Vec::with_capacity(B),
-            items: Vec::with_capacity(B),
-        }
+    fn leaf(&self) -> bool {
+        self.children.len() == 0
     }
 
-    fn open(&self) -> bool {
-        self.items.len() <= B
+    fn search(&self, k: &K) -> Result<usize, usize> {
+        self.items.binary_search_by(|kv| kv.0.cmp(k))
     }
 
-    fn leaf(&self) -> bool {
-        self.children.len() == 0
+    /// Number of items in this subtree
+    fn count(&self) -> usize {
+        self.items.len() + self.children.iter().map(|c| c.borrow().count()).sum::<usize>()
     }
 
     fn split(&mut self) -> ((K, V), BTreePtr<K, V>){
@@ -94,7 +402,6 @@ impl<K: Ord, V> BTreeNode<K, V> {
         let midpoint = self.items.pop().unwrap();
 
         let right = make_ptr(BTreeNode {
-            parent: self.parent.clone() /* clone Option<Rc<_>> */,
             items: right_items,
             children: right_children,
         });
@@ -102,40 +409,90 @@ impl<K: Ord, V> BTreeNode<K, V> {
         (midpoint, right)
     }
 
-    fn upinsert(&mut self, kv: (K, V), right: BTreePtr<K, V>) {
+    /// Insert `kv` and the subtree to its right after the `i`th child
+    fn upinsert(&mut self, i: usize, kv: (K, V), right: BTreePtr<K, V>) {
         debug_assert!(!self.leaf());
 
-        let index = match self.items.binary_search_by(|&(ref l, _)| l.cmp(&kv.0)) {
-            Ok(i) => i,
-            Err(i) => i,
-        };
-        self.items.insert(index, kv);
-        self.children.insert(index, right);
-    }
-
-    fn balance(this: &BTreePtr<K, V>) {
-        if this.borrow().items.len() == B {
-            let (midpoint, right) = this.borrow_mut().split();
-
-            if this.borrow().parent.is_some() {
-                let this = this.borrow();
-                let parent = this.parent.as_ref().unwrap();
-                right.borrow_mut().parent = Some(parent.clone());
-                parent.borrow_mut().upinsert(midpoint, right);
-                BTreeNode::balance(parent);
-            } else {
-                // create new root
-                let mut root = BTreeNode::new_root();
-                root.items.push(midpoint);
-                root.children.push(this.clone());
-                root.children.push(right);
-
-                // reassign parents of left/right
-                let root = make_ptr(root);
-                this.borrow_mut().parent = Some(root.clone());
-                this.borrow_mut().parent = Some(root);
+        self.items.insert(i, kv);
+        self.children.insert(i + 1, right);
+    }
+
+    /// Give the `i`th child one more item, taken through this node from a
+    /// sibling which can spare one, or else merge the child with a sibling
+    fn refill(&mut self, i: usize) {
+        let spare = |c: &BTreePtr<K, V>| c.borrow().items.len() > midpoint();
+        if i > 0 && spare(&self.children[i - 1]) {
+            let (mut left, mut child) = (self.children[i - 1].borrow_mut(), self.children[i].borrow_mut());
+            let kv = left.items.pop().unwrap();
+            child.items.insert(0, mem::replace(&mut self.items[i - 1], kv));
+            if let Some(c) = left.children.pop() {
+                child.children.insert(0, c);
             }
+        } else if i + 1 < self.children.len() && spare(&self.children[i + 1]) {
+            let (mut child, mut right) = (self.children[i].borrow_mut(), self.children[i + 1].borrow_mut());
+            let kv = right.items.remove(0);
+            child.items.push(mem::replace(&mut self.items[i], kv));
+            if !right.leaf() {
+                child.children.push(right.children.remove(0));
+            }
+        } else {
+            let j = if i > 0 { i - 1 } else { i };
+            let right = self.children.remove(j + 1);
+            let kv = self.items.remove(j);
+            let mut left = self.children[j].borrow_mut();
+            let mut right = right.borrow_mut();
+            left.items.push(kv);
+            left.items.append(&mut right.items);
+            left.children.append(&mut right.children);
+        }
+    }
+
+    /// Returns the height and number of items of this subtree
+    #[cfg(test)]
+    fn validate(&self, root: bool, lo: Option<&K>, hi: Option<&K>) -> (usize, usize) {
+        assert!(self.items.len() < B, "Node overflows");
+        assert!(root || self.items.len() >= midpoint(), "Node underflows");
+        assert!(self.items.windows(2).all(|w| w[0].0 < w[1].0), "Items out of order");
+        assert!(self.items.iter().all(|kv| lo.map_or(true, |lo| *lo < kv.0)), "Item below its subtree");
+        assert!(self.items.iter().all(|kv| hi.map_or(true, |hi| kv.0 < *hi)), "Item above its subtree");
+        if self.leaf() {
+            return (1, self.items.len())
+        }
+
+        assert_eq!(self.children.len(), self.items.len() + 1);
+        let mut height = None;
+        let mut count = self.items.len();
+        for (i, c) in self.children.iter().enumerate() {
+            let lo = if i == 0 { lo } else { Some(&self.items[i - 1].0) };
+            let hi = self.items.get(i).map(|kv| &kv.0).or(hi);
+            let (h, n) = c.borrow().validate(false, lo, hi);
+            assert!(height.map_or(true, |height| height == h), "Leaves at different depths");
+            height = Some(h);
+            count += n;
         }
+        (height.unwrap() + 1, count)
+    }
+}
+
+/// Removes the items in ascending order of key
+pub struct IntoIter<K: Ord, V>(BTree<K, V>);
+
+impl<K: Ord, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_first()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len(), Some(self.0.len()))
+    }
+}
+
+impl<K: Ord, V> IntoIterator for BTree<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
     }
 }
 
@@ -143,11 +500,80 @@ impl<K: Ord, V> BTreeNode<K, V> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use util::random_permutation;
 
     #[test]
     fn test_btree() {
         let mut b: BTree<usize, usize> = BTree::new();
         b.insert(3, 5);
-        assert!(b.get)
+        assert!(b.contains_key(&3));
+        assert!(!b.contains_key(&4));
+    }
+
+    #[test]
+    fn test_btree_insert_remove() {
+        let mut b = BTree::new();
+        for k in random_permutation(1000) {
+            assert_eq!(b.insert(k, k * 2), None);
+        }
+        assert_eq!(b.insert(7, 0), Some(14));
+        b.validate();
+        assert_eq!(b.len(), 1000);
+
+        for k in random_permutation(1000).into_iter().filter(|k| k % 2 == 0) {
+            assert_eq!(b.remove(&k), Some(k * 2));
+        }
+        assert_eq!(b.remove(&0), None);
+        b.validate();
+        assert!(b.into_iter().map(|(k, _)| k).eq((0..1000).filter(|k| k % 2 == 1)));
+    }
+
+    #[test]
+    fn test_btree_remove_range() {
+        let mut b = BTree::new();
+        for k in 0..1000 {
+            b.insert(k, ());
+        }
+        b.remove_range(200..800);
+        b.validate();
+
+        assert_eq!(b.len(), 400);
+        assert!(b.into_iter().map(|(k, _)| k).eq((0..200).chain(800..1000)));
+    }
+
+    #[test]
+    fn test_btree_remove_range_bounds() {
+        let ranges: Vec<(Bound<usize>, Bound<usize>)> = vec![
+            (Bound::Unbounded, Bound::Unbounded),
+            (Bound::Unbounded, Bound::Excluded(1)),
+            (Bound::Included(999), Bound::Unbounded),
+            (Bound::Included(500), Bound::Included(500)),
+            (Bound::Excluded(10), Bound::Excluded(11)),
+            (Bound::Included(3), Bound::Included(996)),
+            (Bound::Excluded(40), Bound::Included(77)),
+            (Bound::Included(2000), Bound::Unbounded),
+        ];
+        for range in ranges {
+            let mut b = BTree::new();
+            for k in random_permutation(1000) {
+                b.insert(k, ());
+            }
+            b.remove_range(range);
+            b.validate();
+
+            let after_start = |k: usize| match range.0 {
+                Bound::Included(s) => k >= s,
+                Bound::Excluded(s) => k > s,
+                Bound::Unbounded => true,
+            };
+            let before_end = |k: usize| match range.1 {
+                Bound::Included(e) => k <= e,
+                Bound::Excluded(e) => k < e,
+                Bound::Unbounded => true,
+            };
+            let rest = (0..1000).filter(|&k| !(after_start(k) && before_end(k))).collect::<Vec<_>>();
+            assert_eq!(b.len(), rest.len());
+            assert_eq!(b.into_iter().map(|(k, _)| k).collect::<Vec<_>>(), rest);
+        }
     }
 }
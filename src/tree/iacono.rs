@@ -12,13 +12,19 @@
 //! This structure functions by maintaining a sequence of exponentially growing trees.
 //! When an item is accessed, it is moved from its current tree, to the first and smallest
 //! tree so that it may easily be found again.
+//!
+//! Duplicate keys may be inserted with `insert_dup`. All copies of a key share a
+//! single entry, so accessing any of them promotes them all.
 
 use order::linked_list::{LinkedList, Atom};
 use std::collections::BTreeMap;
 use std::cmp::{Ord, Ordering};
 
+/// A key, its first value, and the values of any duplicates
+type Entry<K, V> = Box<(K, V, Vec<V>)>;
+
 pub struct Iacono<K: Ord, V> {
-    buckets: Vec<Bucket<Repr<K>, Box<(K, V)>>>,
+    buckets: Vec<Bucket<Repr<K>, Entry<K, V>>>,
     len: usize,
 }
 
@@ -34,9 +40,9 @@ impl<K: Ord, V> Iacono<K, V> {
         self.len
     }
 
-    fn find_bucket(&mut self, key: Repr<K>) -> Option<usize> {
+    fn find_bucket(&self, key: Repr<K>) -> Option<usize> {
         let mut i = 0;
-        for bucket in &mut self.buckets {
+        for bucket in &self.buckets {
             if bucket.tree.get(&key).is_some() {
                 return Some(i)
             }
@@ -45,14 +51,14 @@ impl<K: Ord, V> Iacono<K, V> {
         return None
     }
 
-    fn bucket_push(&mut self, index: usize, key: Repr<K>, value: Box<(K, V)>) {
+    fn bucket_push(&mut self, index: usize, key: Repr<K>, value: Entry<K, V>) {
         if index == self.buckets.len() {
             self.buckets.push(Bucket::new())
         }
         self.buckets[index].push(key, value)
     }
 
-    fn bucket_pop(&mut self, index: usize) -> (Repr<K>, Box<(K, V)>) {
+    fn bucket_pop(&mut self, index: usize) -> (Repr<K>, Entry<K, V>) {
         self.buckets[index].pop()
     }
 
@@ -70,8 +76,18 @@ impl<K: Ord, V> Iacono<K, V> {
         }
     }
 
+    /// Move the entry of `key` from bucket `index` to the first bucket
+    fn promote(&mut self, index: usize, key: Repr<K>) -> &mut Entry<K, V> {
+        self.shift_multi(index);
+        let pair = self.buckets[index].remove(&key);
+
+        let key = Repr(&pair.0);
+        self.buckets[0].push(key, pair);
+        self.buckets[0].get_mut(&key).unwrap()
+    }
+
     pub fn insert(&mut self, key: K, value: V) {
-        let pair = Box::new((key, value));
+        let pair = Box::new((key, value, Vec::new()));
         let key = Repr(&pair.0);
 
         if self.find_bucket(key).is_none() {
@@ -82,31 +98,112 @@ impl<K: Ord, V> Iacono<K, V> {
         }
     }
 
+    /// Insert `key` even if it is already present
+    ///
+    /// Inserting a duplicate counts as an access of `key`, so its entry is
+    /// moved to the first bucket.
+    pub fn insert_dup(&mut self, key: K, value: V) {
+        let repr = Repr(&key);
+        if let Some(index) = self.find_bucket(repr) {
+            self.promote(index, repr).2.push(value);
+            self.len += 1;
+        } else {
+            self.insert(key, value)
+        }
+    }
+
+    /// Remove one copy of `key`, the most recently inserted duplicate first
     pub fn remove(&mut self, key: &K) -> Option<V> {
         let key = Repr(key);
 
         if let Some(index) = self.find_bucket(key) {
-            let pair = self.buckets[index].remove(&key);
             self.len -= 1;
+            let dup = self.buckets[index].get_mut(&key).unwrap().2.pop();
+            if dup.is_some() {
+                return dup
+            }
+            let pair = self.buckets[index].remove(&key);
             Some(pair.1)
         } else {
             None
         }
     }
 
+    /// Return the value first inserted with `key`
     pub fn get(&mut self, key: &K) -> Option<&V> {
         let repr = Repr(key);
         if let Some(index) = self.find_bucket(repr) {
-            self.shift_multi(index);
-            let pair = self.buckets[index].remove(&repr);
+            Some(&self.promote(index, repr).1)
+        } else {
+            None
+        }
+    }
 
-            let repr = Repr(&pair.0);
-            self.buckets[0].push(repr, pair);
-            self.buckets[0].tree.get(&repr).map(|&(_, ref v)| &v.1)
+    /// Like `get()` but the value may be modified
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let repr = Repr(key);
+        if let Some(index) = self.find_bucket(repr) {
+            Some(&mut self.promote(index, repr).1)
         } else {
             None
         }
     }
+
+    /// Like `get()` but without counting as an access
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        let repr = Repr(key);
+        self.find_bucket(repr)
+            .and_then(|index| self.buckets[index].tree.get(&repr))
+            .map(|&(_, ref pair)| &pair.1)
+    }
+
+    /// Every value with its key and bucket, for rebuilding with `from_entries()`
+    ///
+    /// Entries are listed bucket by bucket, each bucket from its least to its
+    /// most recently accessed key.
+    pub fn to_entries(&self) -> Vec<(K, V, usize)> where K: Clone, V: Clone {
+        let mut entries = Vec::with_capacity(self.len);
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            for atom in bucket.list.iter() {
+                let pair = &bucket.tree[&atom.get()].1;
+                entries.push((pair.0.clone(), pair.1.clone(), index));
+                for dup in &pair.2 {
+                    entries.push((pair.0.clone(), dup.clone(), index));
+                }
+            }
+        }
+        entries
+    }
+
+    /// Rebuild a structure from the output of `to_entries()`
+    ///
+    /// Repeated keys become duplicates of their first entry.
+    pub fn from_entries<I: IntoIterator<Item = (K, V, usize)>>(entries: I) -> Self {
+        let mut t = Self::new();
+        for (key, value, index) in entries {
+            let repr = Repr(&key);
+            match t.find_bucket(repr) {
+                Some(i) => t.buckets[i].get_mut(&repr).unwrap().2.push(value),
+                None => {
+                    while t.buckets.len() <= index {
+                        t.buckets.push(Bucket::new());
+                    }
+                    let pair = Box::new((key, value, Vec::new()));
+                    let repr = Repr(&pair.0);
+                    t.buckets[index].push(repr, pair);
+                }
+            }
+            t.len += 1;
+        }
+        t
+    }
+
+    /// Index of the bucket holding `key`
+    ///
+    /// Lower buckets hold more recently accessed keys.
+    pub fn bucket_of(&self, key: &K) -> Option<usize> {
+        self.find_bucket(Repr(key))
+    }
 }
 
 struct Bucket<K: Ord + Copy, V> {
@@ -140,6 +237,10 @@ impl<K: Ord+Copy, V> Bucket<K, V> {
         self.list.extract(atom);
         val
     }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.tree.get_mut(key).map(|&mut (_, ref mut v)| v)
+    }
 }
 
 /// A wrapper around *ptrs that is useful
@@ -182,6 +283,63 @@ mod test {
         assert_eq!(t.get(&"c"), Some(&1i32));
     }
 
+    #[test]
+    fn test_iacono_get_mut() {
+        let mut t: Iacono<usize, usize> = Iacono::new();
+        for i in 0..100 {
+            t.insert(i, i);
+        }
+        assert!(t.bucket_of(&0).unwrap() > 0);
+
+        *t.get_mut(&0).unwrap() += 1;
+        assert_eq!(t.peek(&0), Some(&1));
+        assert_eq!(t.bucket_of(&0), Some(0));
+        assert_eq!(t.get_mut(&100), None);
+    }
+
+    #[test]
+    fn test_iacono_entries() {
+        let mut t: Iacono<usize, usize> = Iacono::new();
+        for i in 0..100 {
+            t.insert(i, i * 10);
+        }
+        t.insert_dup(42, 1);
+        t.get(&7);
+
+        let entries = t.to_entries();
+        assert_eq!(entries.len(), 101);
+        assert_eq!(entries.iter().filter(|e| e.2 == 0).last(), Some(&(7, 70, 0)));
+
+        let mut u = Iacono::from_entries(entries.clone());
+        assert_eq!(u.len(), t.len());
+        assert_eq!(u.to_entries(), entries);
+        for i in 0..100 {
+            assert_eq!(u.bucket_of(&i), t.bucket_of(&i));
+            assert_eq!(u.peek(&i), Some(&(i * 10)));
+        }
+        assert_eq!(u.remove(&42), Some(1));
+    }
+
+    #[test]
+    fn test_iacono_dup() {
+        let mut t: Iacono<&str, i32> = Iacono::new();
+        t.insert("b", 0);
+        t.insert_dup("a", 1);
+        t.insert_dup("a", 2);
+        t.insert_dup("a", 3);
+        assert_eq!(t.len(), 4);
+        assert_eq!(t.get(&"a"), Some(&1));
+
+        assert_eq!(t.remove(&"a"), Some(3));
+        assert_eq!(t.len(), 3);
+        assert_eq!(t.remove(&"a"), Some(2));
+        assert_eq!(t.len(), 2);
+        assert_eq!(t.remove(&"a"), Some(1));
+        assert_eq!(t.len(), 1);
+        assert_eq!(t.remove(&"a"), None);
+        assert_eq!(t.get(&"b"), Some(&0));
+    }
+
     #[test]
     fn test_iacono_growth() {
         let mut t: Iacono<usize, ()> = Iacono::new();
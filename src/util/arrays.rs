@@ -0,0 +1,41 @@
+//! Classic input distributions for exercising sorting algorithms
+
+/// `0, 1, ..., l-1`
+pub fn sorted_array(l: usize) -> Vec<usize> {
+    (0..l).collect()
+}
+
+/// `l-1, ..., 1, 0`
+pub fn reverse_array(l: usize) -> Vec<usize> {
+    (0..l).rev().collect()
+}
+
+/// Ascending for the first half, then descending, e.g. `0, 1, 2, 1, 0`
+pub fn organ_pipe(l: usize) -> Vec<usize> {
+    (0..l).map(|i| i.min(l - 1 - i)).collect()
+}
+
+/// Repeated ascending runs of length `period`, e.g. `0, 1, 2, 0, 1, 2, 0`
+pub fn sawtooth(l: usize, period: usize) -> Vec<usize> {
+    assert!(period > 0, "period must be positive");
+    (0..l).map(|i| i % period).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shapes() {
+        assert_eq!(sorted_array(5), [0, 1, 2, 3, 4]);
+        assert_eq!(reverse_array(5), [4, 3, 2, 1, 0]);
+        assert_eq!(organ_pipe(5), [0, 1, 2, 1, 0]);
+        assert_eq!(organ_pipe(6), [0, 1, 2, 2, 1, 0]);
+        assert_eq!(sawtooth(7, 3), [0, 1, 2, 0, 1, 2, 0]);
+
+        assert!(sorted_array(0).is_empty());
+        assert!(organ_pipe(0).is_empty());
+        assert_eq!(reverse_array(1000).len(), 1000);
+        assert_eq!(sawtooth(1000, 7).len(), 1000);
+    }
+}
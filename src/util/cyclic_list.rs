@@ -1,6 +1,6 @@
 //! A cyclically linked list
 
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 use std::cell::{RefCell, Ref};
 use std::ops::Deref;
 use std::clone::Clone;
@@ -10,39 +10,36 @@ use std::clone::Clone;
 /// Currently leaks memory. This is possible to fix but not a priority.
 pub struct CyclicList<T>(Rc<Inner<T>>);
 
+/// A non-owning reference to a node of a `CyclicList`
+pub struct WeakCyclicList<T>(Weak<Inner<T>>);
+
 struct Inner<T> {
     item: T,
-    prev: RefCell<CyclicList<T>>,
-    next: RefCell<CyclicList<T>>,
+    // Only `None` while a node is being constructed or unwrapped
+    prev: RefCell<Option<CyclicList<T>>>,
+    next: RefCell<Option<CyclicList<T>>>,
 }
 
 impl<T> CyclicList<T> {
     pub fn new(item: T) -> Self {
-        use std::mem;
-        use std::ptr;
-
-        unsafe {
-            let list = CyclicList(Rc::new(Inner {
-                item,
-                prev: mem::uninitialized(),
-                next: mem::uninitialized(),
-            }));
+        let list = CyclicList(Rc::new(Inner {
+            item,
+            prev: RefCell::new(None),
+            next: RefCell::new(None),
+        }));
 
-            let prev = list.clone();
-            let next = list.clone();
-            ptr::write(&mut *list.0.prev.borrow_mut(), prev);
-            ptr::write(&mut *list.0.prev.borrow_mut(), next);
+        *list.0.prev.borrow_mut() = Some(list.clone());
+        *list.0.next.borrow_mut() = Some(list.clone());
 
-            list
-        }
+        list
     }
 
     pub fn prev(&self) -> Ref<CyclicList<T>> {
-        self.0.prev.borrow()
+        Ref::map(self.0.prev.borrow(), |p| p.as_ref().unwrap())
     }
 
     pub fn next(&self) -> Ref<CyclicList<T>> {
-        self.0.next.borrow()
+        Ref::map(self.0.next.borrow(), |n| n.as_ref().unwrap())
     }
 
     pub fn is_single(&self) -> bool {
@@ -50,8 +47,8 @@ impl<T> CyclicList<T> {
     }
 
     fn put_behind(&self, other: Self) {
-        *other.0.next.borrow_mut() = self.clone();
-        *self.0.prev.borrow_mut() = other
+        *other.0.next.borrow_mut() = Some(self.clone());
+        *self.0.prev.borrow_mut() = Some(other)
     }
     pub fn push_front(&self, other: Self) {
         debug_assert!(other.is_single());
@@ -85,14 +82,40 @@ impl<T> CyclicList<T> {
         self.put_behind(last);
     }
 
+    /// Unlink this node, leaving it in a list by itself
     pub fn remove(&self) {
         if self.is_single() { return }
-        self.next().put_behind(self.prev().clone());
+        let next = self.next().clone();
+        next.put_behind(self.prev().clone());
+        self.put_behind(self.clone());
     }
 
     pub fn ptr_eq(this: &Self, that: &Self) -> bool {
         Rc::ptr_eq(&this.0, &that.0)
     }
+
+    pub fn downgrade(this: &Self) -> WeakCyclicList<T> {
+        WeakCyclicList(Rc::downgrade(&this.0))
+    }
+
+    /// Returns the item if `this` is the only reference to a single node
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        debug_assert!(this.is_single());
+        this.0.prev.borrow_mut().take();
+        this.0.next.borrow_mut().take();
+
+        Rc::try_unwrap(this.0).map(|inner| inner.item).map_err(|rc| {
+            let list = CyclicList(rc);
+            list.put_behind(list.clone());
+            list
+        })
+    }
+}
+
+impl<T> WeakCyclicList<T> {
+    pub fn upgrade(&self) -> Option<CyclicList<T>> {
+        self.0.upgrade().map(CyclicList)
+    }
 }
 
 impl<T> Deref for CyclicList<T> {
@@ -107,3 +130,9 @@ impl<T> Clone for CyclicList<T> {
         CyclicList(self.0.clone())
     }
 }
+
+impl<T> Clone for WeakCyclicList<T> {
+    fn clone(&self) -> Self {
+        WeakCyclicList(self.0.clone())
+    }
+}
@@ -1,5 +1,11 @@
 pub mod random;
+#[cfg(test)]
+pub mod arrays;
 pub mod cyclic_list;
+pub mod pow2;
 
 pub use self::random::*;
+#[cfg(test)]
+pub use self::arrays::*;
 pub use self::cyclic_list::*;
+pub use self::pow2::*;
@@ -0,0 +1,33 @@
+//! Powers of two
+
+/// Smallest power of two strictly greater than `n`
+pub fn next_power_of_two(n: usize) -> usize {
+    (n + 1).next_power_of_two()
+}
+
+/// Smallest power of two greater than or equal to `n`, and 1 for 0
+pub fn round_up_pow2(n: usize) -> usize {
+    n.next_power_of_two()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn boundaries() {
+        assert_eq!(round_up_pow2(0), 1);
+        assert_eq!(round_up_pow2(1), 1);
+        assert_eq!(round_up_pow2(2), 2);
+        assert_eq!(round_up_pow2(3), 4);
+        assert_eq!(round_up_pow2(1024), 1024);
+        assert_eq!(round_up_pow2(1025), 2048);
+
+        assert_eq!(next_power_of_two(0), 1);
+        assert_eq!(next_power_of_two(1), 2);
+        assert_eq!(next_power_of_two(2), 4);
+        assert_eq!(next_power_of_two(3), 4);
+        assert_eq!(next_power_of_two(1024), 2048);
+        assert_eq!(next_power_of_two(1025), 2048);
+    }
+}
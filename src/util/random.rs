@@ -1,4 +1,5 @@
 use rand;
+#[cfg(test)]
 use rand::Rng;
 use rand::distributions::range::SampleRange;
 
@@ -21,6 +22,7 @@ pub fn random_samples<T: Clone>(array: &[T], k: usize) -> Vec<T> {
     v
 }
 
+#[cfg(test)]
 pub fn random_array(l: usize) -> Vec<usize> {
     let mut rng = rand::thread_rng();
     let mut out = Vec::with_capacity(l);
@@ -29,3 +31,32 @@ pub fn random_array(l: usize) -> Vec<usize> {
     }
     out
 }
+
+/// `0..n` in a uniformly random order (Fisher-Yates shuffle)
+#[cfg(test)]
+pub fn random_permutation(n: usize) -> Vec<usize> {
+    let mut rng = rand::thread_rng();
+    let mut out = (0..n).collect::<Vec<usize>>();
+    for i in (1..n).rev() {
+        let j = rng.gen_range(0, i + 1);
+        out.swap(i, j);
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn permutation() {
+        let p = random_permutation(1000);
+        let mut seen = vec![false; 1000];
+        for &x in &p {
+            assert!(!seen[x]);
+            seen[x] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+        assert!(random_permutation(0).is_empty());
+    }
+}